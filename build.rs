@@ -9,11 +9,22 @@
 //! new memory settings.
 //!
 //! The build script also sets the linker flags to tell it which link script to use.
+//!
+//! Last, it gzips every file under `static/` into the output directory and generates the
+//! table `src/assets.rs` serves them from.
 
+use miniz_oxide::deflate::core::{
+    compress, create_comp_flags_from_zip_params, CompressorOxide, TDEFLFlush, TDEFLStatus,
+};
 use std::env;
-use std::fs::File;
+use std::fmt::Write as _;
+use std::fs::{self, File};
 use std::io::Write;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
+
+const STATIC_DIR: &str = "static";
+// the deflate stream is full flushed after this many bytes, see src/inflate.rs.
+const ASSET_SEGMENT: usize = 2048;
 
 fn main() {
     // Put `memory.x` in our output directory and ensure it's
@@ -42,5 +53,121 @@ fn main() {
     // Set the linker script to the one provided by cortex-m-rt.
     println!("cargo:rustc-link-arg=-Tlink.x");
 
-    println!("cargo:rustc-link-arg=-Tdefmt.x")
+    println!("cargo:rustc-link-arg=-Tdefmt.x");
+
+    // Watching the directory re-runs the script when any asset is added, removed or edited.
+    println!("cargo:rerun-if-changed=build.rs");
+    println!("cargo:rerun-if-changed={STATIC_DIR}");
+    generate_assets(out);
+}
+
+fn generate_assets(out: &Path) {
+    let mut files = Vec::new();
+    collect_files(Path::new(STATIC_DIR), &mut files);
+    files.sort();
+
+    let mut table = String::from("pub static ASSETS: &[Asset] = &[\n");
+    for (index, file) in files.iter().enumerate() {
+        let data = fs::read(file).unwrap();
+        let url = file
+            .strip_prefix(STATIC_DIR)
+            .unwrap()
+            .components()
+            .map(|x| x.as_os_str().to_str().expect("asset names must be utf-8"))
+            .fold(String::new(), |url, x| url + "/" + x);
+        let gzname = format!("asset{index}.gz");
+        fs::write(out.join(&gzname), gzip(&data)).unwrap();
+        writeln!(
+            table,
+            "    Asset {{ path: {url:?}, content_type: {:?}, etag: \"{:016x}\", \
+             data: include_bytes!(concat!(env!(\"OUT_DIR\"), \"/{gzname}\")) }},",
+            mime_type(file),
+            fnv1a(&data),
+        )
+        .unwrap();
+    }
+    table.push_str("];\n");
+    fs::write(out.join("assets.rs"), table).unwrap();
+}
+
+fn collect_files(dir: &Path, files: &mut Vec<PathBuf>) {
+    for entry in fs::read_dir(dir).unwrap() {
+        let path = entry.unwrap().path();
+        if path.is_dir() {
+            collect_files(&path, files);
+        } else {
+            files.push(path);
+        }
+    }
+}
+
+fn mime_type(file: &Path) -> &'static str {
+    match file.extension().and_then(|x| x.to_str()) {
+        Some("html" | "htm") => "text/html",
+        Some("js") => "text/javascript",
+        Some("css") => "text/css",
+        Some("json") => "application/json",
+        Some("svg") => "image/svg+xml",
+        Some("png") => "image/png",
+        Some("ico") => "image/x-icon",
+        Some("txt") => "text/plain",
+        _ => "application/octet-stream",
+    }
+}
+
+// RFC 1952 member around a raw deflate stream.
+fn gzip(data: &[u8]) -> Vec<u8> {
+    let mut out = vec![0x1f, 0x8b, 8, 0, 0, 0, 0, 0, 2, 0xff];
+    out.extend(deflate(data));
+    out.extend(crc32(data).to_le_bytes());
+    out.extend((data.len() as u32).to_le_bytes());
+    out
+}
+
+// every segment is followed by a full flush, so none refers back into the one before it and
+// the board can inflate them one at a time without a window.
+fn deflate(data: &[u8]) -> Vec<u8> {
+    let flags = create_comp_flags_from_zip_params(10, -15, 0);
+    let mut compressor = CompressorOxide::new(flags);
+    let mut segments: Vec<&[u8]> = data.chunks(ASSET_SEGMENT).collect();
+    if segments.is_empty() {
+        segments.push(&[]);
+    }
+
+    let mut out = Vec::new();
+    let mut buf = vec![0; 2 * ASSET_SEGMENT + 1024];
+    for (index, segment) in segments.iter().enumerate() {
+        let flush = if index + 1 == segments.len() {
+            TDEFLFlush::Finish
+        } else {
+            TDEFLFlush::Full
+        };
+        let (status, read, written) = compress(&mut compressor, segment, &mut buf, flush);
+        assert!(
+            matches!(status, TDEFLStatus::Okay | TDEFLStatus::Done)
+                && read == segment.len()
+                && written < buf.len(),
+            "deflate failed"
+        );
+        out.extend_from_slice(&buf[..written]);
+    }
+    out
+}
+
+fn crc32(data: &[u8]) -> u32 {
+    let mut crc = !0u32;
+    for byte in data {
+        crc ^= *byte as u32;
+        for _ in 0..8 {
+            crc = (crc >> 1) ^ (0xedb8_8320 & (crc & 1).wrapping_neg());
+        }
+    }
+    !crc
+}
+
+// the etag, taken over the uncompressed content.
+fn fnv1a(data: &[u8]) -> u64 {
+    data.iter().fold(0xcbf2_9ce4_8422_2325, |hash, x| {
+        (hash ^ *x as u64).wrapping_mul(0x0100_0000_01b3)
+    })
 }
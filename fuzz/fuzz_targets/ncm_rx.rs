@@ -0,0 +1,64 @@
+#![no_main]
+//ncm rx
+//feeds arbitrary usb packets into the ncm rx state machine, none of them may panic it.
+//run from fuzz/ with `cargo fuzz run ncm_rx corpus/ncm_rx seeds/ncm_rx`, seeds/ holds ntbs that
+//once got past the checks.
+
+#[allow(dead_code)]
+#[path = "../../src/cdc_ncm.rs"]
+mod cdc_ncm;
+#[allow(dead_code)]
+#[path = "../../src/ncm_api.rs"]
+mod ncm_api;
+#[allow(dead_code)]
+#[path = "../../src/ncm_netif.rs"]
+mod ncm_netif;
+#[allow(dead_code)]
+#[path = "../../src/usbipserver.rs"]
+mod usbipserver;
+
+use cdc_ncm::EP_DATA_BUF_SIZE;
+use concurrent_queue::ConcurrentQueue;
+use libfuzzer_sys::fuzz_target;
+use ncm_api::NcmApiManager;
+use ncm_netif::StmPhy;
+
+//stands in for the systick counter in main.rs.
+pub fn get_counter() -> u32 {
+    0
+}
+
+//the log goes nowhere, there's no probe on the host.
+#[defmt::global_logger]
+struct Logger;
+
+unsafe impl defmt::Logger for Logger {
+    fn acquire() {}
+    unsafe fn flush() {}
+    unsafe fn release() {}
+    unsafe fn write(_bytes: &[u8]) {}
+}
+
+fuzz_target!(|data: &[u8]| {
+    let mut ncmapi = NcmApiManager::new();
+    let mut phy = StmPhy::new();
+    let mut usbrxring = ConcurrentQueue::bounded(4);
+    let mut usbtxring = ConcurrentQueue::bounded(8);
+
+    //every packet is a length byte followed by that many bytes, up to a full usb packet.
+    let mut data = data;
+    while let Some((&len, rest)) = data.split_first() {
+        let len = (len as usize % (EP_DATA_BUF_SIZE + 1)).min(rest.len());
+        let mut packet = [0u8; EP_DATA_BUF_SIZE];
+        packet[0..len].copy_from_slice(&rest[0..len]);
+        data = &rest[len..];
+
+        usbrxring.push((len, packet)).ok();
+        ncmapi.process_messages(
+            (&mut phy.rxq, &mut phy.txq),
+            (&mut usbrxring, &mut usbtxring),
+        );
+        //the netif takes whatever frames came out.
+        while phy.rxq.pop().is_ok() {}
+    }
+});
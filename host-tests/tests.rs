@@ -0,0 +1,38 @@
+//host tests
+//builds the firmware modules that don't touch the hardware for the host, so their #[cfg(test)]
+//modules run with a plain `cargo test` from host-tests/.
+
+#[allow(dead_code, clippy::from_over_into)]
+#[path = "../src/dhcp.rs"]
+mod dhcp;
+#[allow(dead_code)]
+#[path = "../src/http.rs"]
+mod http;
+#[allow(dead_code)]
+#[path = "../src/inflate.rs"]
+mod inflate;
+#[allow(dead_code)]
+#[path = "../src/json.rs"]
+mod json;
+#[allow(dead_code)]
+#[path = "../src/kvstore.rs"]
+mod kvstore;
+#[allow(dead_code)]
+#[path = "../src/websocket.rs"]
+mod websocket;
+
+//stands in for the uptime in main.rs.
+pub fn get_uptime() -> u64 {
+    0
+}
+
+//the log goes nowhere, there's no probe on the host.
+#[defmt::global_logger]
+struct Logger;
+
+unsafe impl defmt::Logger for Logger {
+    fn acquire() {}
+    unsafe fn flush() {}
+    unsafe fn release() {}
+    unsafe fn write(_bytes: &[u8]) {}
+}
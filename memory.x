@@ -2,7 +2,8 @@ MEMORY
 {
   RAM    (xrw)    : ORIGIN = 0x20000000,   LENGTH = 24K
   RAM2    (rw)    : ORIGIN = 0x20006000,   LENGTH = 16K
-  FLASH    (rx)    : ORIGIN = 0x8000000,   LENGTH = 128K
+  /* the last 4K (2 pages) hold the config store, see kvstore.rs */
+  FLASH    (rx)    : ORIGIN = 0x8000000,   LENGTH = 124K
 }
 
 SECTIONS {
@@ -0,0 +1,106 @@
+//static assets
+//everything under static/, gzipped at build time by build.rs.
+//clients that don't take gzip get it inflated on the fly, so flash only holds one copy.
+extern crate alloc;
+use alloc::boxed::Box;
+use alloc::format;
+use alloc::string::ToString;
+use alloc::vec::Vec;
+use defmt::warn;
+
+use crate::http::{
+    BodyGenerator, HttpBody, HttpContentType, HttpEncodingType, HttpError, HttpRequest,
+    HttpResponse,
+};
+use crate::inflate::Inflater;
+
+// sent with every asset. no-cache still lets the browser keep a copy, it just has to check
+// back with the etag first, and gets an empty 304 while nothing changed. a max-age here
+// saves the round trip as well, at the cost of stale pages for that long after a reflash.
+const CACHE_CONTROL: &str = "no-cache";
+
+pub struct Asset {
+    pub path: &'static str,
+    pub content_type: &'static str,
+    // hash of the uncompressed content.
+    pub etag: &'static str,
+    // gzip compressed.
+    pub data: &'static [u8],
+}
+
+include!(concat!(env!("OUT_DIR"), "/assets.rs"));
+
+pub fn find(path: &str) -> Option<&'static Asset> {
+    let path = if path == "/" { "/index.html" } else { path };
+    ASSETS.iter().find(|x| x.path == path)
+}
+
+// the router's fallback, any path no route claimed is looked up here.
+pub fn serve(request: &HttpRequest) -> Result<HttpResponse, HttpError> {
+    let Some(asset) = find(&request.path) else {
+        return Ok(HttpResponse::not_found());
+    };
+    if request.method != "GET" {
+        return Ok(HttpResponse::method_not_allowed("GET"));
+    }
+    let content_type = HttpContentType::Mime(asset.content_type);
+    let gzip = accepts_gzip(request);
+    // each encoding is its own representation, with its own etag.
+    let etag = match gzip {
+        true => format!("\"{}\"", asset.etag),
+        false => format!("\"{}-identity\"", asset.etag),
+    };
+    let response = match request.header("if-none-match") {
+        Some(tags) if etag_matches(tags, &etag) => HttpResponse::not_modified(),
+        _ if gzip => {
+            HttpResponse::ok(content_type, asset.data).with_encoding(HttpEncodingType::Gzip)
+        }
+        _ => {
+            let inflater = Inflater::new(asset.data).map_err(|_| HttpError::ParseError)?;
+            HttpResponse::ok(content_type, HttpBody::Generator(Box::new(inflater)))
+        }
+    };
+    Ok(response
+        .with_header("ETag", etag)
+        .with_header("Cache-Control", CACHE_CONTROL.to_string())
+        .with_header("Vary", "Accept-Encoding".to_string()))
+}
+
+// no Accept-Encoding at all is taken as identity only, that's what curl and most small
+// clients mean by leaving it out.
+fn accepts_gzip(request: &HttpRequest) -> bool {
+    let Some(encodings) = request.header("accept-encoding") else {
+        return false;
+    };
+    encodings.split(',').any(|x| {
+        let mut parts = x.split(';');
+        let name = parts.next().unwrap_or_default().trim();
+        // q=0 (or 0.0...) rules the encoding out.
+        let refused = parts.any(|x| {
+            x.trim()
+                .strip_prefix("q=")
+                .is_some_and(|q| q.trim_start_matches(['0', '.']).is_empty())
+        });
+        matches!(name, "gzip" | "x-gzip" | "*") && !refused
+    })
+}
+
+// If-None-Match holds "*" or a list of tags, compared weakly (RFC 9110 13.1.2).
+fn etag_matches(tags: &str, etag: &str) -> bool {
+    tags.split(',')
+        .map(|x| x.trim())
+        .any(|x| x == "*" || x.strip_prefix("W/").unwrap_or(x) == etag)
+}
+
+impl BodyGenerator for Inflater {
+    fn fill(&mut self, out: &mut Vec<u8>) -> bool {
+        self.segment(out).unwrap_or_else(|x| {
+            warn!("failed to inflate asset: {}", x);
+            false
+        })
+    }
+
+    fn content_length(&self) -> Option<usize> {
+        Some(self.len())
+    }
+}
@@ -15,6 +15,8 @@ use num_enum::TryFromPrimitive;
 // use serde::Serialize;
 use core::array::TryFromSliceError;
 use usb_device::class_prelude::*;
+
+use crate::ncm_netif::MAX_FRAME_SIZE;
 /// This should be used as `device_class` when building the `UsbDevice`.
 
 //FIXME: a lot of these can be tkaen from original usb_acm rather than redefing..
@@ -31,7 +33,7 @@ const CDC_TYPE_UNION: u8 = 0x06;
 
 const ETH_NET_FUNC_DESC: u8 = 0x0f;
 
-pub const NCM_MAX_SEGMENT_SIZE: u16 = 1514;
+pub const NCM_MAX_SEGMENT_SIZE: u16 = MAX_FRAME_SIZE as u16;
 
 // const USBD_ISTR_INTERFACES: u8 = 0x00;
 
@@ -39,12 +41,82 @@ pub const NCM_MAX_IN_SIZE: usize = 2048;
 pub const NCM_MAX_OUT_SIZE: usize = 2048;
 
 pub const EP_DATA_BUF_SIZE: usize = 64;
+// IN datagrams are aligned to this within the NTB.
+pub const NDP_IN_DIVISOR: usize = 4;
+// the host may batch this many datagrams into an OUT NTB.
+pub const NCM_OUT_MAX_DATAGRAMS: usize = 8;
+
+// wValue bits of SetEthernetPacketFilter (CDC ECM 6.2.4)
+const PACKET_TYPE_PROMISCUOUS: u16 = 0x01;
+const PACKET_TYPE_ALL_MULTICAST: u16 = 0x02;
+const PACKET_TYPE_DIRECTED: u16 = 0x04;
+const PACKET_TYPE_BROADCAST: u16 = 0x08;
+const PACKET_TYPE_MULTICAST: u16 = 0x10;
+
+/// The set of frames the host asked us to deliver on the IN pipe.
+#[derive(Clone, Copy, Debug, defmt::Format)]
+pub struct EthPacketFilter(u16);
+
+impl Default for EthPacketFilter {
+    fn default() -> Self {
+        EthPacketFilter(PACKET_TYPE_DIRECTED | PACKET_TYPE_BROADCAST | PACKET_TYPE_ALL_MULTICAST)
+    }
+}
+
+impl EthPacketFilter {
+    pub fn accepts(&self, dst: &[u8]) -> bool {
+        if self.0 & PACKET_TYPE_PROMISCUOUS != 0 {
+            return true;
+        }
+        if dst.iter().all(|x| *x == 0xff) {
+            self.0 & PACKET_TYPE_BROADCAST != 0
+        } else if dst[0] & 0x01 != 0 {
+            // we expose no multicast address filters, so the filtered mode passes every group.
+            self.0 & (PACKET_TYPE_ALL_MULTICAST | PACKET_TYPE_MULTICAST) != 0
+        } else {
+            self.0 & PACKET_TYPE_DIRECTED != 0
+        }
+    }
+}
+
+/// The NTB format the host selected with SetNtbFormat.
+#[derive(Clone, Copy, Default, PartialEq, Debug, defmt::Format)]
+pub enum NtbFormat {
+    #[default]
+    Ntb16,
+    Ntb32,
+}
+
+impl NtbFormat {
+    // bytes in a length or index field.
+    pub fn width(&self) -> usize {
+        match self {
+            NtbFormat::Ntb16 => 2,
+            NtbFormat::Ntb32 => 4,
+        }
+    }
+
+    pub fn nth_len(&self) -> usize {
+        8 + 2 * self.width()
+    }
+
+    // an NDP without its datagram entries.
+    pub fn ndp_len(&self) -> usize {
+        4 * self.width()
+    }
+
+    pub fn entry_len(&self) -> usize {
+        2 * self.width()
+    }
+}
 
 #[derive(Debug, defmt::Format, TryFromPrimitive)]
 #[repr(u8)]
 enum CDCRequests {
     SetEthernetPacketFilter = 0x43,
     GetNTBParameters = 0x80,
+    GetNtbFormat = 0x83,
+    SetNtbFormat = 0x84,
     GetNTBInputSize = 0x85,
     SetNTBInputSize = 0x86,
 }
@@ -57,6 +129,10 @@ pub struct CdcNcmClass<'a, B: UsbBus> {
     write_ep: EndpointIn<'a, B>,
     namestr: StringIndex,
     macaddrstr: StringIndex,
+    packet_filter: EthPacketFilter,
+    ntb_format: NtbFormat,
+    // alt 1 of the data interface has the endpoints, alt 0 is the function held in reset.
+    data_alt: u8,
 }
 
 #[repr(C, packed)]
@@ -79,16 +155,16 @@ struct NCMParameters {
 const LEN: usize = size_of::<NCMParameters>();
 const PARAMS: NCMParameters = NCMParameters {
     length: LEN as u16,
-    ntb_formats_supported: 1,
+    ntb_formats_supported: 3,
     ntb_in_maxsize: NCM_MAX_IN_SIZE as u32,
-    ndp_in_divisor: 4,
+    ndp_in_divisor: NDP_IN_DIVISOR as u16,
     ndp_in_alignment: 4,
     ndp_in_payload_remainder: 0,
     ntb_out_maxsize: NCM_MAX_OUT_SIZE as u32,
     ndp_out_divisor: 4,
     ndp_out_alignment: 4,
     ndp_out_payload_remainder: 0,
-    ntb_out_max_datagrams: 1,
+    ntb_out_max_datagrams: NCM_OUT_MAX_DATAGRAMS as u16,
     reserved: 0,
 };
 
@@ -193,9 +269,18 @@ impl<B: UsbBus> CdcNcmClass<'_, B> {
             write_ep: alloc.alloc(None, EndpointType::Bulk, EP_DATA_BUF_SIZE as u16, 1).unwrap(),
             namestr: alloc.string(),
             macaddrstr: alloc.string(),
+            packet_filter: EthPacketFilter::default(),
+            ntb_format: NtbFormat::default(),
+            data_alt: 0,
         }
     }
 
+    // what the host set up goes back to the defaults, a new host may not set it again.
+    fn reset_function(&mut self) {
+        self.packet_filter = EthPacketFilter::default();
+        self.ntb_format = NtbFormat::default();
+    }
+
     /// Writes a single packet into the IN endpoint.
     pub fn write_packet(&mut self, data: &[u8]) -> Result<usize, UsbError> {
         self.write_ep.write(data)
@@ -209,6 +294,14 @@ impl<B: UsbBus> CdcNcmClass<'_, B> {
     pub fn send_notification(&mut self, data: &[u8]) -> Result<usize, UsbError> {
         self.ned_ep.write(data)
     }
+
+    pub fn packet_filter(&self) -> EthPacketFilter {
+        self.packet_filter
+    }
+
+    pub fn ntb_format(&self) -> NtbFormat {
+        self.ntb_format
+    }
 }
 
 impl<B: UsbBus> UsbClass<B> for CdcNcmClass<'_, B> {
@@ -315,6 +408,19 @@ impl<B: UsbBus> UsbClass<B> for CdcNcmClass<'_, B> {
                         let ntbsize: u32 = u32::from_le_bytes(data[0..3].try_into().unwrap());
                         info!("computer requested NTBsize of {}", ntbsize);
                     }
+                    CDCRequests::SetEthernetPacketFilter => {
+                        self.packet_filter = EthPacketFilter(req.value);
+                        debug!("packet filter set to {:02x}", req.value);
+                        xfer.accept().ok();
+                    }
+                    CDCRequests::SetNtbFormat if req.value <= 1 => {
+                        self.ntb_format = match req.value {
+                            0 => NtbFormat::Ntb16,
+                            _ => NtbFormat::Ntb32,
+                        };
+                        info!("ntb format set to {}", self.ntb_format);
+                        xfer.accept().ok();
+                    }
                     _ => xfer.reject().ok().unwrap(),
                 }
                 // gracefully accept the transfer and skip for now.
@@ -354,6 +460,13 @@ impl<B: UsbBus> UsbClass<B> for CdcNcmClass<'_, B> {
                         })
                         .ok();
                     }
+                    CDCRequests::GetNtbFormat => {
+                        xfer.accept(|data| {
+                            data[0..2].copy_from_slice(&(self.ntb_format as u16).to_le_bytes());
+                            Ok(2)
+                        })
+                        .ok();
+                    }
                     CDCRequests::GetNTBInputSize => {
                         xfer.accept(|data| {
                             data[0..3].copy_from_slice(&NCM_MAX_SEGMENT_SIZE.to_le_bytes());
@@ -371,14 +484,28 @@ impl<B: UsbBus> UsbClass<B> for CdcNcmClass<'_, B> {
             }
         }
     }
+    fn reset(&mut self) {
+        self.data_alt = 0;
+        self.reset_function();
+    }
+
     fn get_alt_setting(&mut self, interface: InterfaceNumber) -> Option<u8> {
         if interface == self.data_if {
-            Some(1)
+            Some(self.data_alt)
         } else {
             None
         }
     }
+
+    // NCM 7.2: selecting alt 0 of the data interface resets the function.
     fn set_alt_setting(&mut self, interface: InterfaceNumber, alternative: u8) -> bool {
-        (interface, alternative) == (self.data_if, 1)
+        if interface != self.data_if || alternative > 1 {
+            return false;
+        }
+        if alternative == 0 {
+            self.reset_function();
+        }
+        self.data_alt = alternative;
+        true
     }
 }
@@ -3,7 +3,7 @@ use core::mem;
 use core::mem::size_of;
 
 use alloc::vec;
-use defmt::info;
+use defmt::{info, warn};
 use num_enum::IntoPrimitive;
 use num_enum::TryFromPrimitive;
 use smoltcp::wire::Ipv4Address;
@@ -16,10 +16,22 @@ pub const DHCP_CLIENT_PORT: u16 = 68;
 const DHCP_CHADDR_LEN: usize = 16;
 const DHCP_SNAME_LEN: usize = 64;
 const DHCP_FILE_LEN: usize = 128;
-const DHCP_OPTIONS_LEN: usize = 68;
+const DHCP_OPTIONS_LEN: usize = 128;
+
+const SNAMESTART: usize = 44;
+const FILESTART: usize = SNAMESTART + DHCP_SNAME_LEN;
+const COOKIESTART: usize = FILESTART + DHCP_FILE_LEN;
+const OPTIONSTART: usize = COOKIESTART + 4;
 
 const DHCP_MAGIC_COOKIE: u32 = 0x63825363;
 
+// option overload (52) values, RFC 2132 9.3
+const OVERLOAD_FILE: u8 = 1;
+const OVERLOAD_SNAME: u8 = 2;
+
+// the BROADCAST bit of flags (RFC 2131 2), the top bit of the field's first byte on the wire.
+const FLAG_BROADCAST: u8 = 0x80;
+
 #[derive(Debug, Clone, Copy, defmt::Format, IntoPrimitive)]
 #[repr(u8)]
 enum DhcpOpcodes {
@@ -58,6 +70,10 @@ enum DhcpOptionTypes {
     Overload = 52,
     MsgType = 53,
     ServerId = 54,
+    ParamRequestList = 55,
+    ClientId = 61,
+    CaptivePortal = 114,
+    ClasslessRoute = 121,
     End = 255,
 }
 #[derive(defmt::Format, Debug)]
@@ -85,40 +101,149 @@ trait ToBytes {
     fn conv_to_bytes(&self) -> Vec<u8>;
 }
 
-impl ToBytes for Vec<OptionU32Msg> {
+impl ToBytes for Vec<OptionMsg> {
     fn conv_to_bytes(&self) -> Vec<u8> {
         let mut out = Vec::<u8>::new();
         self.iter().for_each(|x| {
-            let buf: [u8; 6] = x.into();
-            out.extend_from_slice(&buf[0..2 + (x.len as usize)])
+            out.push(x.type_.into());
+            out.push(x.data.len() as u8);
+            out.extend_from_slice(&x.data)
         });
         out
     }
 }
 
 #[derive(defmt::Format, Debug)]
-struct OptionU32Msg {
+struct OptionMsg {
     type_: DhcpOptionTypes,
-    len: u8,
-    data: [u8; 4],
+    data: Vec<u8>,
 }
 
-impl From<&OptionU32Msg> for [u8; 6] {
-    fn from(value: &OptionU32Msg) -> Self {
-        let mut arr = [0u8; 6];
-        arr[0] = value.type_.into();
-        arr[1] = value.len;
-        arr[2..].copy_from_slice(&value.data);
-        arr
+impl OptionMsg {
+    fn new(type_: DhcpOptionTypes, data: &[u8]) -> Self {
+        OptionMsg {
+            type_,
+            data: data.to_vec(),
+        }
     }
 }
-impl From<&[u8]> for OptionU32Msg {
-    fn from(value: &[u8]) -> Self {
-        let type_ = DhcpOptionTypes::try_from_primitive(value[0]).unwrap();
-        let len = value[1];
-        let mut data = [0u8; 4];
-        data[0..(len as usize)].copy_from_slice(&value[2..2 + (len as usize)]);
-        OptionU32Msg { type_, len, data }
+
+// a single option as found on the wire, codes we don't know are passed through as is.
+struct DhcpOption<'a> {
+    code: u8,
+    data: &'a [u8],
+}
+
+// walks the options area, then the file and sname fields if option 52 says they carry options.
+struct DhcpOptionIter<'a> {
+    areas: [&'a [u8]; 3],
+    area: usize,
+    offset: usize,
+    overload: u8,
+}
+
+impl<'a> DhcpOptionIter<'a> {
+    // `msg` must hold at least the fixed header and the cookie.
+    fn new(msg: &'a [u8]) -> Self {
+        DhcpOptionIter {
+            areas: [
+                &msg[OPTIONSTART..],
+                &msg[FILESTART..COOKIESTART],
+                &msg[SNAMESTART..FILESTART],
+            ],
+            area: 0,
+            offset: 0,
+            overload: 0,
+        }
+    }
+
+    fn next_area(&mut self) -> bool {
+        let next = match self.area {
+            0 if self.overload & OVERLOAD_FILE != 0 => 1,
+            0 | 1 if self.overload & OVERLOAD_SNAME != 0 => 2,
+            _ => return false,
+        };
+        self.area = next;
+        self.offset = 0;
+        true
+    }
+}
+
+impl<'a> Iterator for DhcpOptionIter<'a> {
+    type Item = DhcpOption<'a>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let buf = self.areas[self.area];
+            let code = buf.get(self.offset).copied();
+
+            if code == Some(DhcpOptionTypes::Pad.into()) {
+                self.offset += 1;
+                continue;
+            }
+
+            // a missing end marker or a truncated option closes the area as well.
+            let data = match code {
+                Some(code) if code != u8::from(DhcpOptionTypes::End) => buf
+                    .get(self.offset + 1)
+                    .and_then(|len| buf.get(self.offset + 2..self.offset + 2 + *len as usize)),
+                _ => None,
+            };
+
+            match (code, data) {
+                (Some(code), Some(data)) => {
+                    self.offset += 2 + data.len();
+                    if self.area == 0 && code == DhcpOptionTypes::Overload.into() && data.len() == 1
+                    {
+                        self.overload = data[0];
+                    }
+                    return Some(DhcpOption { code, data });
+                }
+                _ => {
+                    if !self.next_area() {
+                        return None;
+                    }
+                }
+            }
+        }
+    }
+}
+
+// typed view of the options we act on.
+#[derive(Default, defmt::Format)]
+struct DhcpRequestOptions<'a> {
+    msg_type: Option<DhcpMsgTypes>,
+    requested_ip: Option<Ipv4Address>,
+    server_id: Option<Ipv4Address>,
+    param_request: &'a [u8],
+    client_id: Option<&'a [u8]>,
+    hostname: Option<&'a str>,
+}
+
+impl<'a> From<DhcpOptionIter<'a>> for DhcpRequestOptions<'a> {
+    fn from(value: DhcpOptionIter<'a>) -> Self {
+        let ipv4 = |data: &[u8]| (data.len() == 4).then(|| Ipv4Address::from_bytes(data));
+        let mut opts = DhcpRequestOptions::default();
+
+        for opt in value {
+            match DhcpOptionTypes::try_from_primitive(opt.code) {
+                Ok(DhcpOptionTypes::MsgType) => {
+                    opts.msg_type = opt
+                        .data
+                        .first()
+                        .and_then(|x| DhcpMsgTypes::try_from_primitive(*x).ok())
+                }
+                Ok(DhcpOptionTypes::Requestedip) => opts.requested_ip = ipv4(opt.data),
+                Ok(DhcpOptionTypes::ServerId) => opts.server_id = ipv4(opt.data),
+                Ok(DhcpOptionTypes::ParamRequestList) => opts.param_request = opt.data,
+                Ok(DhcpOptionTypes::ClientId) => opts.client_id = Some(opt.data),
+                Ok(DhcpOptionTypes::Hostname) => {
+                    opts.hostname = core::str::from_utf8(opt.data).ok()
+                }
+                _ => (),
+            }
+        }
+        opts
     }
 }
 
@@ -147,9 +272,6 @@ impl Default for DhcpMsg {
 
 impl From<&[u8]> for DhcpMsg {
     fn from(value: &[u8]) -> Self {
-        const FILESTART: usize = 44 + DHCP_SNAME_LEN;
-        const COOKIESTART: usize = 44 + DHCP_SNAME_LEN + DHCP_FILE_LEN;
-        const OPTIONSTART: usize = 44 + DHCP_SNAME_LEN + DHCP_FILE_LEN + 4;
         const OPTIONSEND: usize = OPTIONSTART + DHCP_OPTIONS_LEN;
         let opts: [u8; DHCP_OPTIONS_LEN] = match value.len().cmp(&OPTIONSEND) {
             cmp::Ordering::Less => {
@@ -174,13 +296,20 @@ impl From<&[u8]> for DhcpMsg {
             siaddr: Ipv4Address::from_bytes(value[20..24].try_into().unwrap()),
             giaddr: Ipv4Address::from_bytes(value[24..28].try_into().unwrap()),
             chaddr: value[28..44].try_into().unwrap(),
-            sname: value[44..FILESTART].try_into().unwrap(),
+            sname: value[SNAMESTART..FILESTART].try_into().unwrap(),
             file: value[FILESTART..COOKIESTART].try_into().unwrap(),
             cookie: u32::from_le_bytes(value[COOKIESTART..OPTIONSTART].try_into().unwrap()),
             options: opts,
         }
     }
 }
+impl DhcpMsg {
+    // flags is kept in wire order, like the other fields.
+    fn broadcast(&self) -> bool {
+        self.flags.to_le_bytes()[0] & FLAG_BROADCAST != 0
+    }
+}
+
 impl Into<Vec<u8>> for DhcpMsg {
     fn into(self) -> Vec<u8> {
         // let mut buf = vec![self.op, self.htype, self.hlen, self.hops];
@@ -189,84 +318,469 @@ impl Into<Vec<u8>> for DhcpMsg {
     }
 }
 
+// how long an offered address is held for the client that was offered it.
+const DHCP_OFFER_HOLD_MS: u64 = 30_000;
+// addresses a client declined (RFC 2131 3.1.5) are kept out of the pool for this long.
+const DHCP_DECLINE_HOLD_MS: u64 = 600_000;
+// client, host part, state and seconds left, as kept in the config store.
+const SAVED_LEASE_LEN: usize = 12;
+const MAX_SAVED_LEASES: usize = 32;
+
+#[derive(Clone, Copy, defmt::Format)]
+pub struct DhcpConfig {
+    pub lease_time: u32, // in seconds.
+    // offer the board as the default gateway (option 3).
+    pub router: bool,
+    // offer the board as dns server (option 6).
+    pub dns: bool,
+    // offer a route to the board's subnet only (option 121), for hosts that should keep their own default route.
+    pub classless_routes: bool,
+    // uri of the RFC 8908 captive portal api on our http server (option 114).
+    pub captive_portal: Option<&'static str>,
+    // the interface mtu the host should use (option 26).
+    pub mtu: Option<u16>,
+}
+
+impl Default for DhcpConfig {
+    fn default() -> Self {
+        DhcpConfig {
+            lease_time: 86400,
+            router: true,
+            dns: true,
+            classless_routes: false,
+            captive_portal: None,
+            mtu: None,
+        }
+    }
+}
+
+impl DhcpConfig {
+    // keeps the host's own internet connection, the board is only reachable on its own subnet.
+    pub fn no_default_route() -> Self {
+        DhcpConfig {
+            router: false,
+            dns: false,
+            classless_routes: true,
+            ..DhcpConfig::default()
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, defmt::Format)]
+pub enum LeaseState {
+    Offered,
+    Bound,
+    Released,
+    Declined,
+}
+
+#[derive(Debug, Clone, Copy, defmt::Format)]
+pub struct DhcpLease {
+    pub client: [u8; 6], // supports only EUI-48 addresses.
+    pub addr: u8,        // host part of the leased address.
+    pub state: LeaseState,
+    pub expires: u64, // in ms since boot.
+}
+
+impl DhcpLease {
+    fn expired(&self, now: u64) -> bool {
+        now >= self.expires
+    }
+
+    // whether the address can be handed to another client.
+    fn reusable(&self, now: u64) -> bool {
+        match self.state {
+            LeaseState::Released => true,
+            _ => self.expired(now),
+        }
+    }
+}
+
+pub struct DhcpReply {
+    pub msg: Vec<u8>,
+    pub dest: Ipv4Address,
+}
+
 #[derive(Default)]
 pub struct DhcpServer {
     pub addrstart: u8,
-    pub maxaddr: u8,
-    pub addrcnt: u8,
+    pub maxaddr: u8, // last host address of the pool, inclusive.
+    pub addrcnt: u8, // where to look for the next unused address in the pool.
     pub serverip: Ipv4Address,
     pub subnet: Ipv4Address,
-    pub allocated: Vec<[u8; 6]>, // supports only EUI-48 addresses.
+    pub config: DhcpConfig,
+    pub leases: Vec<DhcpLease>,
+    // set when a lease is bound or given up, so the table gets saved.
+    pub leases_changed: bool,
 }
 
 impl DhcpServer {
-    pub fn recv(&mut self, buf: &[u8]) -> Option<Vec<u8>> {
+    pub fn recv(&mut self, buf: &[u8], now: u64) -> Option<DhcpReply> {
+        if buf.len() < OPTIONSTART
+            || buf[COOKIESTART..OPTIONSTART] != DHCP_MAGIC_COOKIE.to_be_bytes()
+        {
+            warn!("dropping malformed dhcp message");
+            return None;
+        }
         let incoming: DhcpMsg = buf.into();
         // info!("msg: {:?}", incoming);
 
-        //convert the first section into an option
-        let req_opt: OptionU32Msg = incoming.options[0..6].into();
-        info!("req: {:?}", req_opt);
-        match DhcpMsgTypes::try_from_primitive(req_opt.data[0]).unwrap() {
+        let opts: DhcpRequestOptions = DhcpOptionIter::new(buf).into();
+        info!("req: {:?}", opts);
+
+        let mut client = [0u8; 6];
+        client.copy_from_slice(&incoming.chaddr[0..6]);
+        let addressed_to_us = opts.server_id.is_none_or(|x| x == self.serverip);
+
+        match opts.msg_type? {
             DhcpMsgTypes::Discover => {
-                Some(self.create_dhcp_reply(incoming, DhcpMsgTypes::Offer).into())
+                let Some(addr) = self.offer(&client, opts.requested_ip, now) else {
+                    warn!("dhcp pool exhausted, not answering discover");
+                    return None;
+                };
+                Some(self.reply(incoming, DhcpMsgTypes::Offer, addr, opts.param_request))
+            }
+            DhcpMsgTypes::Request if !addressed_to_us => {
+                // the client picked another server, let our offer go.
+                self.release(&client, now);
+                None
             }
             DhcpMsgTypes::Request => {
-                Some(self.create_dhcp_reply(incoming, DhcpMsgTypes::Ack).into())
+                // selecting and init-reboot clients name the address in option 50, renewing ones in ciaddr.
+                let ciaddr = incoming.ciaddr;
+                let target = opts
+                    .requested_ip
+                    .or((!ciaddr.is_unspecified()).then_some(ciaddr));
+                let granted = match target {
+                    Some(ip) => self
+                        .host_part(ip)
+                        .and_then(|host| self.claim(&client, host, now)),
+                    None => self.leases.iter().position(|x| x.client == client),
+                };
+
+                match granted {
+                    Some(idx) => {
+                        let expires = now + self.lease_ms();
+                        let lease = &mut self.leases[idx];
+                        // renewals only move the expiry, which isn't worth a flash write.
+                        self.leases_changed |= lease.state != LeaseState::Bound;
+                        lease.state = LeaseState::Bound;
+                        lease.expires = expires;
+                        let addr = lease.addr;
+                        let addr = self.address(addr);
+                        info!("leased {} to {:?}", addr, opts.hostname);
+                        Some(self.reply(incoming, DhcpMsgTypes::Ack, addr, opts.param_request))
+                    }
+                    None => {
+                        warn!("nak for {:?}", target);
+                        Some(self.reply(
+                            incoming,
+                            DhcpMsgTypes::Nak,
+                            Ipv4Address::UNSPECIFIED,
+                            opts.param_request,
+                        ))
+                    }
+                }
+            }
+            DhcpMsgTypes::Decline if addressed_to_us => {
+                let addr = self.host_part(opts.requested_ip?)?;
+                if let Some(lease) = self
+                    .leases
+                    .iter_mut()
+                    .find(|x| x.addr == addr && x.client == client)
+                {
+                    warn!("address {} declined, quarantining it", addr);
+                    self.leases_changed = true;
+                    lease.client = [0u8; 6];
+                    lease.state = LeaseState::Declined;
+                    lease.expires = now + DHCP_DECLINE_HOLD_MS;
+                }
+                None
+            }
+            DhcpMsgTypes::Release if addressed_to_us => {
+                self.release(&client, now);
+                None
+            }
+            DhcpMsgTypes::Inform if addressed_to_us && self.in_subnet(incoming.ciaddr) => {
+                // the client already has an address, it only wants the configuration.
+                Some(self.reply(
+                    incoming,
+                    DhcpMsgTypes::Ack,
+                    Ipv4Address::UNSPECIFIED,
+                    opts.param_request,
+                ))
             }
             _ => None,
         }
     }
 
-    fn create_dhcp_reply(&mut self, incoming: DhcpMsg, msg_type: DhcpMsgTypes) -> DhcpMsg {
-        let mut options = Vec::<OptionU32Msg>::new();
-        let mut ipbuf: [u8; 4] = [0u8; 4];
+    // bound and released leases, so a client gets the same address back after a reset.
+    pub fn save_leases(&self, now: u64) -> Vec<u8> {
+        let mut buf = Vec::<u8>::new();
+        self.leases
+            .iter()
+            .filter(|x| x.client != [0u8; 6])
+            .filter(|x| matches!(x.state, LeaseState::Bound | LeaseState::Released))
+            .take(MAX_SAVED_LEASES)
+            .for_each(|x| {
+                let left = match x.state {
+                    LeaseState::Bound => (x.expires.saturating_sub(now) / 1000) as u32,
+                    _ => 0,
+                };
+                buf.extend_from_slice(&x.client);
+                buf.push(x.addr);
+                buf.push((x.state == LeaseState::Bound) as u8);
+                buf.extend_from_slice(&left.to_be_bytes());
+            });
+        buf
+    }
+
+    pub fn restore_leases(&mut self, buf: &[u8], now: u64) {
+        for saved in buf.chunks_exact(SAVED_LEASE_LEN) {
+            let addr = saved[6];
+            // the pool may have changed since the table was saved.
+            if self.host_part(self.address(addr)).is_none()
+                || self.leases.iter().any(|x| x.addr == addr)
+            {
+                continue;
+            }
+            let left = u32::from_be_bytes(saved[8..12].try_into().unwrap());
+            let (state, expires) = match saved[7] {
+                1 => (
+                    LeaseState::Bound,
+                    now + (left as u64 * 1000).min(self.lease_ms()),
+                ),
+                _ => (LeaseState::Released, now),
+            };
+            self.leases.push(DhcpLease {
+                client: saved[0..6].try_into().unwrap(),
+                addr,
+                state,
+                expires,
+            });
+        }
+        info!("restored {} dhcp leases", self.leases.len());
+    }
+
+    fn lease_ms(&self) -> u64 {
+        self.config.lease_time as u64 * 1000
+    }
+
+    fn address(&self, host: u8) -> Ipv4Address {
+        let mut ip = self.serverip;
+        ip.0[3] = host;
+        ip
+    }
+
+    // whether `ip` is the address of another host on the subnet we serve.
+    fn in_subnet(&self, ip: Ipv4Address) -> bool {
+        let mask = u32::from_be_bytes(self.subnet.0);
+        let (ip, ours) = (u32::from_be_bytes(ip.0), u32::from_be_bytes(self.serverip.0));
+        (ip ^ ours) & mask == 0 && ip != ours && ip & !mask != 0 && ip | mask != u32::MAX
+    }
+
+    // the host part of `ip` if it is an address of our pool.
+    fn host_part(&self, ip: Ipv4Address) -> Option<u8> {
+        let host = ip.0[3];
+        let ours = self.address(host) == ip;
+        (ours && (self.addrstart..=self.maxaddr).contains(&host) && self.in_subnet(ip))
+            .then_some(host)
+    }
+
+    fn release(&mut self, client: &[u8; 6], now: u64) {
+        if let Some(lease) = self
+            .leases
+            .iter_mut()
+            .find(|x| &x.client == client && x.state != LeaseState::Declined)
+        {
+            self.leases_changed |= lease.state == LeaseState::Bound;
+            lease.state = LeaseState::Released;
+            lease.expires = now;
+        }
+    }
+
+    // hands `host` to `client` if it already has it or nobody else holds it.
+    fn claim(&mut self, client: &[u8; 6], host: u8, now: u64) -> Option<usize> {
+        let idx = match self.leases.iter().position(|x| x.addr == host) {
+            Some(idx) if &self.leases[idx].client == client || self.leases[idx].reusable(now) => {
+                idx
+            }
+            Some(_) => return None,
+            None => {
+                self.leases.push(DhcpLease {
+                    client: [0u8; 6],
+                    addr: host,
+                    state: LeaseState::Released,
+                    expires: now,
+                });
+                self.leases.len() - 1
+            }
+        };
+
+        // a client moving to another address gives up the one it had.
+        self.leases
+            .iter_mut()
+            .filter(|x| &x.client == client && x.addr != host)
+            .for_each(|x| {
+                x.client = [0u8; 6];
+                x.state = LeaseState::Released;
+                x.expires = now;
+            });
+        self.leases[idx].client = *client;
+        Some(idx)
+    }
+
+    fn offer(
+        &mut self,
+        client: &[u8; 6],
+        requested: Option<Ipv4Address>,
+        now: u64,
+    ) -> Option<Ipv4Address> {
+        // a client keeps its address, otherwise it gets the one it asked for if that is free.
+        let idx = match self.leases.iter().position(|x| &x.client == client) {
+            Some(idx) => idx,
+            None => requested
+                .and_then(|ip| self.host_part(ip))
+                .and_then(|host| self.claim(client, host, now))
+                .or_else(|| self.allocate(now))?,
+        };
+
+        let lease = &mut self.leases[idx];
+        lease.client = *client;
+        // an address that is still bound keeps its lease until the client asks again.
+        if lease.state != LeaseState::Bound || lease.expired(now) {
+            lease.state = LeaseState::Offered;
+            lease.expires = now + DHCP_OFFER_HOLD_MS;
+        }
+        let addr = lease.addr;
+        Some(self.address(addr))
+    }
+
+    // finds room for a new lease: an address never handed out, otherwise the one that has been free the longest.
+    fn allocate(&mut self, now: u64) -> Option<usize> {
+        let poolsize = self.maxaddr.checked_sub(self.addrstart)? as u16 + 1;
+        for _ in 0..poolsize {
+            let host = self.addrstart + self.addrcnt;
+            self.addrcnt = ((self.addrcnt as u16 + 1) % poolsize) as u8;
+            // a pool that reaches past the subnet, or over its network or broadcast address,
+            // only hands out the hosts in between.
+            if self.in_subnet(self.address(host)) && !self.leases.iter().any(|x| x.addr == host) {
+                self.leases.push(DhcpLease {
+                    client: [0u8; 6],
+                    addr: host,
+                    state: LeaseState::Released,
+                    expires: now,
+                });
+                return Some(self.leases.len() - 1);
+            }
+        }
+
+        self.leases
+            .iter()
+            .enumerate()
+            .filter(|(_, x)| x.reusable(now))
+            .min_by_key(|(_, x)| x.expires)
+            .map(|(idx, _)| idx)
+    }
+
+    fn reply(
+        &self,
+        incoming: DhcpMsg,
+        msg_type: DhcpMsgTypes,
+        yiaddr: Ipv4Address,
+        params: &[u8],
+    ) -> DhcpReply {
+        // RFC 2131 4.1: clients with an address on our subnet get a unicast, unless they set the
+        // broadcast flag. we can't answer arp for a yiaddr the client doesn't use yet, so clients
+        // without one are always broadcast to.
+        let ciaddr = incoming.ciaddr;
+        let dest = match msg_type {
+            DhcpMsgTypes::Nak => Ipv4Address::BROADCAST,
+            _ if incoming.broadcast() || !self.in_subnet(ciaddr) => Ipv4Address::BROADCAST,
+            _ => ciaddr,
+        };
+        DhcpReply {
+            msg: self
+                .create_dhcp_reply(incoming, msg_type, yiaddr, params)
+                .into(),
+            dest,
+        }
+    }
+
+    fn create_dhcp_reply(
+        &self,
+        incoming: DhcpMsg,
+        msg_type: DhcpMsgTypes,
+        yiaddr: Ipv4Address,
+        params: &[u8],
+    ) -> DhcpMsg {
+        let mut options = Vec::<OptionMsg>::new();
+        let serverip = self.serverip.as_bytes();
 
         //create the option info
         // step 1: header.
-        options.push(OptionU32Msg {
-            type_: DhcpOptionTypes::MsgType,
-            len: 1,
-            data: [msg_type.into(), 0, 0, 0],
-        });
+        options.push(OptionMsg::new(DhcpOptionTypes::MsgType, &[msg_type.into()]));
 
-        //subnet mask
-        ipbuf.copy_from_slice(self.subnet.as_bytes());
-        options.push(OptionU32Msg {
-            type_: DhcpOptionTypes::Subnetmask,
-            len: 4,
-            data: ipbuf,
-        });
+        //server id
+        options.push(OptionMsg::new(DhcpOptionTypes::ServerId, serverip));
+
+        // a nak carries nothing but the server id (RFC 2131 table 3).
+        if !matches!(msg_type, DhcpMsgTypes::Nak) {
+            //subnet mask
+            options.push(OptionMsg::new(
+                DhcpOptionTypes::Subnetmask,
+                self.subnet.as_bytes(),
+            ));
+
+            //gateway
+            if self.config.router {
+                options.push(OptionMsg::new(DhcpOptionTypes::Router, serverip));
+            }
 
-        //gateway
-        ipbuf.copy_from_slice(self.serverip.as_bytes());
-        options.push(OptionU32Msg {
-            type_: DhcpOptionTypes::Router,
-            len: 4,
-            data: ipbuf,
-        });
+            // a route to our own subnet only, clients that take it ignore option 3 (RFC 3442).
+            // the subnet is on-link, so its router is 0.0.0.0.
+            if self.config.classless_routes
+                && params.contains(&DhcpOptionTypes::ClasslessRoute.into())
+            {
+                let mask = u32::from_be_bytes(self.subnet.0);
+                let network = (u32::from_be_bytes(self.serverip.0) & mask).to_be_bytes();
+                let prefix = mask.count_ones() as u8;
+                let mut route = vec![prefix];
+                route.extend_from_slice(&network[0..(prefix as usize).div_ceil(8)]);
+                route.extend_from_slice(&[0; 4]);
+                options.push(OptionMsg::new(DhcpOptionTypes::ClasslessRoute, &route));
+            }
 
-        //server id
-        // ipbuf.copy_from_slice(self.serverip.as_bytes());
-        options.push(OptionU32Msg {
-            type_: DhcpOptionTypes::ServerId,
-            len: 4,
-            data: ipbuf,
-        });
+            // RFC 8910: the captive portal api on our http server, only for clients that ask for it.
+            if let Some(uri) = self
+                .config
+                .captive_portal
+                .filter(|_| params.contains(&DhcpOptionTypes::CaptivePortal.into()))
+            {
+                options.push(OptionMsg::new(
+                    DhcpOptionTypes::CaptivePortal,
+                    uri.as_bytes(),
+                ));
+            }
 
-        //lease time
-        options.push(OptionU32Msg {
-            type_: DhcpOptionTypes::Leasetime,
-            len: 4,
-            data: 86400u32.to_le_bytes(),
-        });
+            //lease time, not sent in reply to an inform.
+            if !yiaddr.is_unspecified() {
+                options.push(OptionMsg::new(
+                    DhcpOptionTypes::Leasetime,
+                    &self.config.lease_time.to_be_bytes(),
+                ));
+            }
 
-        //dns server
-        options.push(OptionU32Msg {
-            type_: DhcpOptionTypes::Dnsserver,
-            len: 4,
-            data: ipbuf,
-        });
+            //dns server
+            if self.config.dns {
+                options.push(OptionMsg::new(DhcpOptionTypes::Dnsserver, serverip));
+            }
+
+            //interface mtu
+            if let Some(mtu) = self.config.mtu {
+                options.push(OptionMsg::new(DhcpOptionTypes::Mtu, &mtu.to_be_bytes()));
+            }
+        }
 
         //convert to vec<u8>
         let mut optionbytes: Vec<u8> = options.conv_to_bytes();
@@ -279,37 +793,210 @@ impl DhcpServer {
         DhcpMsg {
             op: DhcpOpcodes::BootReply.into(),
             secs: 0,
-            flags: 0,
             options,
-            yiaddr: self.create_lease(&incoming.chaddr[0..6]),
+            yiaddr,
             ..incoming
         }
-        //TODO: when parsing message do not give new leases to users with ip
     }
+}
 
-    fn create_lease(&mut self, requester: &[u8]) -> Ipv4Address {
-        let mut buf = [0u8;6];
-        buf.copy_from_slice(requester);
-        let mut ip = self.serverip;
-        // if this requester already was leased an address, return the same address.
-        let idx: Vec<usize> = self.allocated.iter().enumerate().filter(|(_, x)| x.as_slice() == requester).map(|(idx,_)| idx).collect::<Vec<_>>();
-        
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // a request from `client` with `options` after the cookie, the rest of the header zeroed.
+    fn message(client: [u8; 6], options: &[u8]) -> Vec<u8> {
+        let mut buf = vec![0u8; OPTIONSTART];
+        buf[0] = 1;
+        buf[28..34].copy_from_slice(&client);
+        buf[COOKIESTART..OPTIONSTART].copy_from_slice(&DHCP_MAGIC_COOKIE.to_be_bytes());
+        buf.extend_from_slice(options);
+        buf
+    }
 
+    fn codes(buf: &[u8]) -> Vec<(u8, Vec<u8>)> {
+        DhcpOptionIter::new(buf)
+            .map(|x| (x.code, x.data.to_vec()))
+            .collect()
+    }
 
+    #[test]
+    fn options_with_pad_and_end() {
+        // message type discover, two pads, hostname, end, then trailing garbage.
+        let buf = message(
+            [1; 6],
+            &[53, 1, 1, 0, 0, 12, 3, b'p', b'c', b'1', 255, 53, 1, 3],
+        );
+        assert_eq!(codes(&buf), [(53, vec![1]), (12, b"pc1".to_vec())]);
+
+        let opts: DhcpRequestOptions = DhcpOptionIter::new(&buf).into();
+        assert!(matches!(opts.msg_type, Some(DhcpMsgTypes::Discover)));
+        assert_eq!(opts.hostname, Some("pc1"));
+    }
 
-        if idx.len() > 1{
-            panic!("somehow we allocated two ips to 1 mac address");
-        }
+    #[test]
+    fn truncated_option_closes_the_area() {
+        // the length runs past the end of the message.
+        let buf = message([1; 6], &[53, 1, 3, 50, 4, 192, 168]);
+        assert_eq!(codes(&buf), [(53, vec![3])]);
+        // a length byte is missing altogether, and so is the end marker.
+        let buf = message([1; 6], &[53, 1, 3, 50]);
+        assert_eq!(codes(&buf), [(53, vec![3])]);
+
+        // an address that isn't four bytes long is ignored.
+        let buf = message([1; 6], &[50, 3, 192, 168, 69]);
+        let opts: DhcpRequestOptions = DhcpOptionIter::new(&buf).into();
+        assert_eq!(opts.requested_ip, None);
+    }
+
+    #[test]
+    fn overloaded_file_then_sname() {
+        let mut buf = message(
+            [1; 6],
+            &[52, 1, OVERLOAD_FILE | OVERLOAD_SNAME, 53, 1, 3, 255],
+        );
+        buf[FILESTART..FILESTART + 7].copy_from_slice(&[50, 4, 192, 168, 69, 9, 255]);
+        buf[SNAMESTART..SNAMESTART + 6].copy_from_slice(&[12, 3, b'p', b'c', b'2', 255]);
+        let found: Vec<u8> = codes(&buf).into_iter().map(|x| x.0).collect();
+        assert_eq!(found, [52, 53, 50, 12]);
+
+        let opts: DhcpRequestOptions = DhcpOptionIter::new(&buf).into();
+        assert_eq!(opts.requested_ip, Some(Ipv4Address::new(192, 168, 69, 9)));
+        assert_eq!(opts.hostname, Some("pc2"));
+
+        // without option 52 the fields are just the file and server names.
+        let mut buf = message([1; 6], &[53, 1, 3, 255]);
+        buf[FILESTART..FILESTART + 7].copy_from_slice(&[50, 4, 192, 168, 69, 9, 255]);
+        assert_eq!(codes(&buf).len(), 1);
+    }
 
-        if idx.is_empty(){
-            ip.0[3] = self.addrstart + self.addrcnt;
-            self.addrcnt += 1;
-            self.allocated.push(buf);
-            
+    const DAY_MS: u64 = 86_400_000;
+    const SERVER: Ipv4Address = Ipv4Address::new(192, 168, 69, 1);
+
+    fn server(addrstart: u8, maxaddr: u8, subnet: Ipv4Address) -> DhcpServer {
+        DhcpServer {
+            addrstart,
+            maxaddr,
+            serverip: SERVER,
+            subnet,
+            ..DhcpServer::default()
         }
-        else{
-            ip.0[3] =self.addrstart + idx[0] as u8;
+    }
+
+    // sends `msg_type` from `client`, with `requested` in option 50. returns the type of the reply
+    // and the address it hands out.
+    fn send(
+        server: &mut DhcpServer,
+        client: u8,
+        msg_type: DhcpMsgTypes,
+        requested: Option<u8>,
+        now: u64,
+    ) -> Option<(DhcpMsgTypes, u8)> {
+        let mut options = vec![53, 1, msg_type.into()];
+        if let Some(host) = requested {
+            options.extend_from_slice(&[50, 4, 192, 168, 69, host]);
         }
-        ip
+        options.push(255);
+        let reply = server.recv(&message([client; 6], &options), now)?;
+        let opts: DhcpRequestOptions = DhcpOptionIter::new(&reply.msg).into();
+        Some((opts.msg_type.unwrap(), reply.msg[19]))
+    }
+
+    // discover and request, what a client does to get an address.
+    fn bind(server: &mut DhcpServer, client: u8, now: u64) -> u8 {
+        let (_, offered) = send(server, client, DhcpMsgTypes::Discover, None, now).unwrap();
+        let reply = send(server, client, DhcpMsgTypes::Request, Some(offered), now);
+        assert!(matches!(reply, Some((DhcpMsgTypes::Ack, x)) if x == offered));
+        offered
+    }
+
+    fn state_of(server: &DhcpServer, host: u8) -> Option<LeaseState> {
+        server
+            .leases
+            .iter()
+            .find(|x| x.addr == host)
+            .map(|x| x.state)
+    }
+
+    #[test]
+    fn leases_are_bound_and_kept_apart() {
+        let mut server = server(5, 7, Ipv4Address::new(255, 255, 255, 0));
+        assert_eq!(bind(&mut server, 1, 0), 5);
+        assert!(server.leases_changed);
+        assert_eq!(state_of(&server, 5), Some(LeaseState::Bound));
+        assert_eq!(bind(&mut server, 2, 0), 6);
+
+        // the held address is refused to anybody else, asking again gets the same one.
+        let reply = send(&mut server, 2, DhcpMsgTypes::Request, Some(5), 1000);
+        assert!(matches!(reply, Some((DhcpMsgTypes::Nak, 0))));
+        assert_eq!(bind(&mut server, 1, 1000), 5);
+
+        // an address outside the pool is refused as well.
+        let reply = send(&mut server, 3, DhcpMsgTypes::Request, Some(200), 1000);
+        assert!(matches!(reply, Some((DhcpMsgTypes::Nak, _))));
+
+        assert_eq!(bind(&mut server, 3, 0), 7);
+        assert!(send(&mut server, 4, DhcpMsgTypes::Discover, None, 0).is_none());
+    }
+
+    #[test]
+    fn released_and_expired_leases_are_reused() {
+        let mut server = server(5, 5, Ipv4Address::new(255, 255, 255, 0));
+        // longer than the 24.8 days a signed millisecond counter lasts.
+        server.config.lease_time = 40 * 86_400;
+        assert_eq!(bind(&mut server, 1, 0), 5);
+        assert!(send(&mut server, 2, DhcpMsgTypes::Discover, None, 30 * DAY_MS).is_none());
+        assert_eq!(bind(&mut server, 2, 41 * DAY_MS), 5);
+
+        send(&mut server, 2, DhcpMsgTypes::Release, None, 41 * DAY_MS);
+        assert_eq!(state_of(&server, 5), Some(LeaseState::Released));
+        assert_eq!(bind(&mut server, 1, 41 * DAY_MS), 5);
+    }
+
+    #[test]
+    fn declined_address_is_held_back() {
+        let mut server = server(5, 6, Ipv4Address::new(255, 255, 255, 0));
+        assert_eq!(bind(&mut server, 1, 0), 5);
+        send(&mut server, 1, DhcpMsgTypes::Decline, Some(5), 0);
+        assert_eq!(state_of(&server, 5), Some(LeaseState::Declined));
+
+        let reply = send(&mut server, 1, DhcpMsgTypes::Discover, Some(5), 1000);
+        assert!(matches!(reply, Some((DhcpMsgTypes::Offer, 6))));
+        let later = DHCP_DECLINE_HOLD_MS + 1000;
+        let reply = send(&mut server, 2, DhcpMsgTypes::Discover, Some(5), later);
+        assert!(matches!(reply, Some((DhcpMsgTypes::Offer, 5))));
+    }
+
+    #[test]
+    fn pool_stays_inside_the_subnet() {
+        // 192.168.69.0/25, 127 is its broadcast address and 128 on are outside of it.
+        let mut server = server(126, 130, Ipv4Address::new(255, 255, 255, 128));
+        assert_eq!(bind(&mut server, 1, 0), 126);
+        assert!(send(&mut server, 2, DhcpMsgTypes::Discover, None, 0).is_none());
+        let reply = send(&mut server, 2, DhcpMsgTypes::Request, Some(129), 0);
+        assert!(matches!(reply, Some((DhcpMsgTypes::Nak, _))));
+    }
+
+    #[test]
+    fn saved_leases_survive_a_reset() {
+        let mut server1 = server(5, 9, Ipv4Address::new(255, 255, 255, 0));
+        assert_eq!(bind(&mut server1, 1, 0), 5);
+        assert_eq!(bind(&mut server1, 2, 0), 6);
+        send(&mut server1, 2, DhcpMsgTypes::Release, None, 0);
+        let saved = server1.save_leases(DAY_MS / 2);
+        assert_eq!(saved.len(), 2 * SAVED_LEASE_LEN);
+
+        let mut server2 = server(5, 9, Ipv4Address::new(255, 255, 255, 0));
+        server2.restore_leases(&saved, 0);
+        assert_eq!(state_of(&server2, 5), Some(LeaseState::Bound));
+        assert_eq!(state_of(&server2, 6), Some(LeaseState::Released));
+        // the rest of the lease is kept, not a fresh one.
+        assert_eq!(server2.leases[0].expires, DAY_MS / 2);
+        assert_eq!(bind(&mut server2, 1, 0), 5);
+
+        // leases that no longer fit the pool are dropped.
+        let mut server3 = server(6, 9, Ipv4Address::new(255, 255, 255, 0));
+        server3.restore_leases(&saved, 0);
+        assert_eq!(state_of(&server3, 5), None);
     }
 }
@@ -0,0 +1,289 @@
+//DNS
+//answers lookups for the board's own hostname, refuses everything else.
+
+extern crate alloc;
+use alloc::string::String;
+use alloc::vec::Vec;
+
+use defmt::debug;
+use num_enum::IntoPrimitive;
+use num_enum::TryFromPrimitive;
+use smoltcp::wire::Ipv4Address;
+
+pub const DNS_SERVER_PORT: u16 = 53;
+pub const DNS_HEADER_LEN: usize = 12;
+const DNS_TTL: u32 = 60;
+const DNS_CLASS_IN: u16 = 1;
+//names are at most 255 bytes, so this bounds any pointer loop.
+const DNS_MAX_POINTERS: usize = 16;
+
+const FLAG_QR: u16 = 0x8000;
+const FLAG_AA: u16 = 0x0400;
+const FLAG_RD: u16 = 0x0100;
+pub const OPCODE_MASK: u16 = 0x7800;
+
+#[derive(Debug, Clone, Copy, PartialEq, defmt::Format, IntoPrimitive, TryFromPrimitive)]
+#[repr(u16)]
+pub enum DnsRecordTypes {
+    A = 1,
+    Ptr = 12,
+    Txt = 16,
+    Aaaa = 28,
+    Srv = 33,
+    Any = 255,
+}
+
+#[derive(Debug, Clone, Copy, defmt::Format, IntoPrimitive)]
+#[repr(u8)]
+pub enum DnsRcodes {
+    NoError = 0,
+    FormErr = 1,
+    NxDomain = 3,
+    NotImp = 4,
+    Refused = 5,
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct DnsHeader {
+    pub id: u16,
+    pub flags: u16,
+    pub qdcount: u16,
+    pub ancount: u16,
+    pub nscount: u16,
+    pub arcount: u16,
+}
+
+impl DnsHeader {
+    pub fn parse(buf: &[u8]) -> Option<Self> {
+        if buf.len() < DNS_HEADER_LEN {
+            return None;
+        }
+        let word = |idx: usize| u16::from_be_bytes([buf[idx], buf[idx + 1]]);
+        Some(DnsHeader {
+            id: word(0),
+            flags: word(2),
+            qdcount: word(4),
+            ancount: word(6),
+            nscount: word(8),
+            arcount: word(10),
+        })
+    }
+
+    pub fn emit(&self, out: &mut Vec<u8>) {
+        for word in [
+            self.id,
+            self.flags,
+            self.qdcount,
+            self.ancount,
+            self.nscount,
+            self.arcount,
+        ] {
+            out.extend_from_slice(&word.to_be_bytes());
+        }
+    }
+
+    pub fn is_response(&self) -> bool {
+        self.flags & FLAG_QR != 0
+    }
+}
+
+pub struct DnsQuestion {
+    pub name: String,
+    pub qtype: u16,
+    pub qclass: u16,
+}
+
+impl DnsQuestion {
+    //returns the question and the offset right after it.
+    pub fn parse(buf: &[u8], offset: usize) -> Option<(Self, usize)> {
+        let (name, offset) = parse_name(buf, offset)?;
+        let fields = buf.get(offset..offset + 4)?;
+        let question = DnsQuestion {
+            name,
+            qtype: u16::from_be_bytes([fields[0], fields[1]]),
+            qclass: u16::from_be_bytes([fields[2], fields[3]]),
+        };
+        Some((question, offset + 4))
+    }
+
+    pub fn emit(&self, out: &mut Vec<u8>) {
+        write_name(out, &self.name);
+        out.extend_from_slice(&self.qtype.to_be_bytes());
+        out.extend_from_slice(&self.qclass.to_be_bytes());
+    }
+
+    pub fn record_type(&self) -> Option<DnsRecordTypes> {
+        DnsRecordTypes::try_from_primitive(self.qtype).ok()
+    }
+}
+
+//decodes a (possibly compressed) name into dotted form.
+pub fn parse_name(buf: &[u8], mut offset: usize) -> Option<(String, usize)> {
+    let mut name = String::new();
+    let mut end = None;
+    let mut pointers = 0;
+
+    loop {
+        let len = *buf.get(offset)? as usize;
+        match len & 0xc0 {
+            0x00 if len == 0 => {
+                return Some((name, end.unwrap_or(offset + 1)));
+            }
+            0x00 => {
+                let label = buf.get(offset + 1..offset + 1 + len)?;
+                if !name.is_empty() {
+                    name.push('.');
+                }
+                name.push_str(core::str::from_utf8(label).ok()?);
+                offset += 1 + len;
+            }
+            0xc0 => {
+                pointers += 1;
+                if pointers > DNS_MAX_POINTERS {
+                    return None;
+                }
+                let lo = *buf.get(offset + 1)? as usize;
+                end.get_or_insert(offset + 2);
+                offset = ((len & 0x3f) << 8) | lo;
+            }
+            _ => return None,
+        }
+    }
+}
+
+pub fn write_name(out: &mut Vec<u8>, name: &str) {
+    name.split('.')
+        .filter(|label| !label.is_empty())
+        .for_each(|label| {
+            out.push(label.len() as u8);
+            out.extend_from_slice(label.as_bytes());
+        });
+    out.push(0);
+}
+
+pub fn write_record(
+    out: &mut Vec<u8>,
+    name: &str,
+    rtype: DnsRecordTypes,
+    class: u16,
+    ttl: u32,
+    rdata: &[u8],
+) {
+    write_name(out, name);
+    out.extend_from_slice(&u16::from(rtype).to_be_bytes());
+    out.extend_from_slice(&class.to_be_bytes());
+    out.extend_from_slice(&ttl.to_be_bytes());
+    out.extend_from_slice(&(rdata.len() as u16).to_be_bytes());
+    out.extend_from_slice(rdata);
+}
+
+//the in-addr.arpa name used for reverse lookups of an address.
+pub fn reverse_name(ip: Ipv4Address) -> String {
+    let b = ip.as_bytes();
+    alloc::format!("{}.{}.{}.{}.in-addr.arpa", b[3], b[2], b[1], b[0])
+}
+
+pub struct DnsServer {
+    pub hostname: String,
+    pub serverip: Ipv4Address,
+}
+
+impl DnsServer {
+    pub fn recv(&mut self, buf: &[u8]) -> Option<Vec<u8>> {
+        let header = DnsHeader::parse(buf)?;
+        if header.is_response() {
+            return None;
+        }
+
+        let mut reply = DnsHeader {
+            id: header.id,
+            flags: FLAG_QR | (header.flags & (OPCODE_MASK | FLAG_RD)),
+            ..DnsHeader::default()
+        };
+        let mut out = Vec::<u8>::new();
+
+        if header.flags & OPCODE_MASK != 0 {
+            reply.flags |= u16::from(u8::from(DnsRcodes::NotImp));
+            reply.emit(&mut out);
+            return Some(out);
+        }
+
+        let question = match DnsQuestion::parse(buf, DNS_HEADER_LEN) {
+            Some((question, _)) if header.qdcount == 1 => question,
+            _ => {
+                reply.flags |= u16::from(u8::from(DnsRcodes::FormErr));
+                reply.emit(&mut out);
+                return Some(out);
+            }
+        };
+        debug!("dns query for {}", question.name.as_str());
+
+        let mut answer = Vec::<u8>::new();
+        let rcode = self.answer(&question, &mut answer);
+        if !matches!(rcode, DnsRcodes::Refused) {
+            reply.flags |= FLAG_AA;
+        }
+        reply.flags |= u16::from(u8::from(rcode));
+        reply.qdcount = 1;
+        reply.ancount = (!answer.is_empty()) as u16;
+
+        reply.emit(&mut out);
+        question.emit(&mut out);
+        out.extend_from_slice(&answer);
+        Some(out)
+    }
+
+    fn answer(&self, question: &DnsQuestion, out: &mut Vec<u8>) -> DnsRcodes {
+        if question.qclass != DNS_CLASS_IN {
+            return DnsRcodes::Refused;
+        }
+        let qtype = question.record_type();
+
+        if question.name.eq_ignore_ascii_case(&self.hostname) {
+            // a name we own but with a type we don't have is answered with no records.
+            if matches!(qtype, Some(DnsRecordTypes::A | DnsRecordTypes::Any)) {
+                write_record(
+                    out,
+                    &self.hostname,
+                    DnsRecordTypes::A,
+                    DNS_CLASS_IN,
+                    DNS_TTL,
+                    self.serverip.as_bytes(),
+                );
+            }
+            return DnsRcodes::NoError;
+        }
+
+        if question
+            .name
+            .eq_ignore_ascii_case(&reverse_name(self.serverip))
+        {
+            if matches!(qtype, Some(DnsRecordTypes::Ptr | DnsRecordTypes::Any)) {
+                let mut rdata = Vec::<u8>::new();
+                write_name(&mut rdata, &self.hostname);
+                write_record(
+                    out,
+                    &question.name,
+                    DnsRecordTypes::Ptr,
+                    DNS_CLASS_IN,
+                    DNS_TTL,
+                    &rdata,
+                );
+            }
+            return DnsRcodes::NoError;
+        }
+
+        // we are authoritative for the hostname's domain, anything else is not ours to answer.
+        match self.hostname.split_once('.') {
+            Some((_, domain)) if in_domain(&question.name, domain) => DnsRcodes::NxDomain,
+            _ => DnsRcodes::Refused,
+        }
+    }
+}
+
+fn in_domain(name: &str, domain: &str) -> bool {
+    let (name, domain) = (name.as_bytes(), domain.as_bytes());
+    name.len() > domain.len()
+        && name[name.len() - domain.len() - 1] == b'.'
+        && name[name.len() - domain.len()..].eq_ignore_ascii_case(domain)
+}
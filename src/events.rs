@@ -0,0 +1,42 @@
+//server-sent events
+//other subsystems publish here, every open event stream picks up the latest event.
+//there is a single slot, a stream that falls behind only ever sees the newest one.
+extern crate alloc;
+use alloc::format;
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::cell::RefCell;
+use critical_section::{with, Mutex};
+
+use crate::http::BodyGenerator;
+
+// sequence number, event name and data of the latest event.
+static LATEST: Mutex<RefCell<(u32, &str, String)>> =
+    Mutex::new(RefCell::new((0, "", String::new())));
+
+// `data` has to be a single line.
+pub fn publish(event: &'static str, data: String) {
+    with(|cs| {
+        let mut latest = LATEST.borrow(cs).borrow_mut();
+        *latest = (latest.0.wrapping_add(1), event, data);
+    })
+}
+
+// the body of a text/event-stream response, it never ends.
+#[derive(Default)]
+pub struct EventStream {
+    seq: u32,
+}
+
+impl BodyGenerator for EventStream {
+    fn fill(&mut self, out: &mut Vec<u8>) -> bool {
+        with(|cs| {
+            let (seq, event, data) = &*LATEST.borrow(cs).borrow();
+            if *seq != self.seq {
+                self.seq = *seq;
+                out.extend_from_slice(format!("event: {event}\ndata: {data}\n\n").as_bytes());
+            }
+        });
+        true
+    }
+}
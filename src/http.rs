@@ -1,30 +1,63 @@
 extern crate alloc;
+use alloc::boxed::Box;
 use alloc::format;
-use alloc::string::{FromUtf8Error, String};
+use alloc::string::{FromUtf8Error, String, ToString};
 use alloc::vec::Vec;
+use defmt::warn;
 
-pub type CallbackBt = Vec<&'static dyn HttpCallback>;
+use smoltcp::wire::IpAddress;
 
-pub const SUPPORTED_METHODS: [&str; 2] = ["GET", "POST"];
+use crate::get_uptime;
+use crate::json::JsonValue;
+use crate::websocket::{base64, WebSocketHandler};
 
+pub type RouteHandler = fn(&HttpRequest) -> Result<HttpResponse, HttpError>;
+
+pub const SUPPORTED_METHODS: [&str; 3] = ["GET", "POST", "PUT"];
+// request line and headers have to fit in this, bodies in HTTP_MAX_BODY.
+const HTTP_MAX_HEAD: usize = 1024;
+const HTTP_MAX_BODY: usize = 256;
+// basic auth is checked as this user, with the token as the password.
+const AUTH_USER: &str = "admin";
+// a peer with this many failed attempts, each within AUTH_LOCKOUT_MS of the last, is locked
+// out until AUTH_LOCKOUT_MS after its last one.
+const AUTH_MAX_FAILURES: u32 = 5;
+const AUTH_LOCKOUT_MS: u64 = 30_000;
+// peers whose failures are remembered, the one that failed longest ago makes room.
+const AUTH_MAX_PEERS: usize = 4;
+
+#[derive(Debug, defmt::Format)]
 pub enum HttpError {
     ParseError,
-    CallbackNotFound,
     Unsupported,
+    // a request body in a transfer coding we don't decode.
+    NotImplemented,
+    // more of the request has to arrive before it can be handled.
+    Incomplete,
+    TooLarge,
 }
 
 pub enum HttpContentType {
     Text,
-    Script,
+    Plain,
     Data,
+    CaptivePortal,
+    Json,
+    EventStream,
+    // any other type, the asset table carries its own.
+    Mime(&'static str),
 }
 
 impl HttpContentType {
     fn as_str(&self) -> &'static str {
         match self {
-            HttpContentType::Data => "Content-Type: application/data\r\n",
-            HttpContentType::Text => "Content-Type: text/html\r\n",
-            HttpContentType::Script => "Content-Type: text/javascript\r\n"
+            HttpContentType::Data => "application/data",
+            HttpContentType::Text => "text/html",
+            HttpContentType::Plain => "text/plain",
+            HttpContentType::CaptivePortal => "application/captive+json",
+            HttpContentType::Json => "application/json",
+            HttpContentType::EventStream => "text/event-stream",
+            HttpContentType::Mime(x) => x,
         }
     }
 }
@@ -49,73 +82,901 @@ impl From<FromUtf8Error> for HttpError {
     }
 }
 
-pub fn gen_http_header(
-    data: Option<&[u8]>,
-    content_type: HttpContentType,
-    encoding_type: Option<HttpEncodingType>,
-) -> Vec<u8> {
-    let lenstr = format!("Content-Length: {}\r\n", data.unwrap_or(&[]).len());
+#[derive(Debug, Clone, Copy, PartialEq, defmt::Format)]
+pub enum HttpStatus {
+    SwitchingProtocols,
+    Ok,
+    NotModified,
+    BadRequest,
+    Unauthorized,
+    NotFound,
+    MethodNotAllowed,
+    PayloadTooLarge,
+    TooManyRequests,
+    InternalError,
+    NotImplemented,
+    Unavailable,
+}
+
+impl HttpStatus {
+    fn as_str(&self) -> &'static str {
+        match self {
+            HttpStatus::SwitchingProtocols => "101 Switching Protocols",
+            HttpStatus::Ok => "200 OK",
+            HttpStatus::NotModified => "304 Not Modified",
+            HttpStatus::BadRequest => "400 Bad Request",
+            HttpStatus::Unauthorized => "401 Unauthorized",
+            HttpStatus::NotFound => "404 Not Found",
+            HttpStatus::MethodNotAllowed => "405 Method Not Allowed",
+            HttpStatus::PayloadTooLarge => "413 Content Too Large",
+            HttpStatus::TooManyRequests => "429 Too Many Requests",
+            HttpStatus::InternalError => "500 Internal Server Error",
+            HttpStatus::NotImplemented => "501 Not Implemented",
+            HttpStatus::Unavailable => "503 Service Unavailable",
+        }
+    }
+}
+
+// produces a body piece by piece. unless its length is known up front it is sent chunked.
+pub trait BodyGenerator {
+    // appends the next part of the body to `out`, returns false once the body is complete.
+    fn fill(&mut self, out: &mut Vec<u8>) -> bool;
+
+    fn content_length(&self) -> Option<usize> {
+        None
+    }
+}
+
+pub enum HttpBody {
+    // sent straight from flash.
+    Static(&'static [u8]),
+    Owned(Vec<u8>),
+    Generator(Box<dyn BodyGenerator>),
+}
+
+impl From<&'static [u8]> for HttpBody {
+    fn from(value: &'static [u8]) -> Self {
+        HttpBody::Static(value)
+    }
+}
+
+impl<const N: usize> From<&'static [u8; N]> for HttpBody {
+    fn from(value: &'static [u8; N]) -> Self {
+        HttpBody::Static(value)
+    }
+}
+
+impl From<Vec<u8>> for HttpBody {
+    fn from(value: Vec<u8>) -> Self {
+        HttpBody::Owned(value)
+    }
+}
+
+impl From<String> for HttpBody {
+    fn from(value: String) -> Self {
+        HttpBody::Owned(value.into_bytes())
+    }
+}
+
+pub struct HttpResponse {
+    pub status: HttpStatus,
+    pub content_type: HttpContentType,
+    pub encoding: HttpEncodingType,
+    pub headers: Vec<(&'static str, String)>,
+    pub body: HttpBody,
+    // set by `websocket::accept`, the connection is handed over after the 101.
+    pub upgrade: Option<Box<dyn WebSocketHandler>>,
+}
+
+impl HttpResponse {
+    pub fn new(
+        status: HttpStatus,
+        content_type: HttpContentType,
+        body: impl Into<HttpBody>,
+    ) -> Self {
+        HttpResponse {
+            status,
+            content_type,
+            encoding: HttpEncodingType::None,
+            headers: Vec::new(),
+            body: body.into(),
+            upgrade: None,
+        }
+    }
+
+    pub fn ok(content_type: HttpContentType, body: impl Into<HttpBody>) -> Self {
+        Self::new(HttpStatus::Ok, content_type, body)
+    }
+
+    pub fn with_encoding(mut self, encoding: HttpEncodingType) -> Self {
+        self.encoding = encoding;
+        self
+    }
+
+    pub fn with_header(mut self, name: &'static str, value: String) -> Self {
+        self.headers.push((name, value));
+        self
+    }
+
+    pub fn json(status: HttpStatus, value: &JsonValue) -> Self {
+        Self::new(status, HttpContentType::Json, value.to_string())
+    }
+
+    // every error carries the same json object, {"status":404,"error":"Not Found"}.
+    pub fn error(status: HttpStatus) -> Self {
+        let (code, reason) = status.as_str().split_once(' ').unwrap();
+        let body = format!("{{\"status\":{code},\"error\":\"{reason}\"}}");
+        Self::new(status, HttpContentType::Json, body)
+    }
+
+    // the client's cached copy is still good, a 304 never has a body.
+    pub fn not_modified() -> Self {
+        Self::new(HttpStatus::NotModified, HttpContentType::Plain, b"")
+    }
+
+    pub fn bad_request() -> Self {
+        Self::error(HttpStatus::BadRequest)
+    }
+
+    pub fn not_found() -> Self {
+        Self::error(HttpStatus::NotFound)
+    }
+
+    pub fn method_not_allowed(allow: &str) -> Self {
+        Self::error(HttpStatus::MethodNotAllowed).with_header("Allow", allow.into())
+    }
+
+    pub fn payload_too_large() -> Self {
+        Self::error(HttpStatus::PayloadTooLarge)
+    }
+
+    #[allow(dead_code)]
+    pub fn internal_error() -> Self {
+        Self::error(HttpStatus::InternalError)
+    }
+
+    #[allow(dead_code)]
+    pub fn unavailable() -> Self {
+        Self::error(HttpStatus::Unavailable)
+    }
+
+    pub fn into_stream(self, keep_alive: bool) -> HttpStream {
+        let status = self.status.as_str();
+        let contentstr = self.content_type.as_str();
+        let encodingstr = self.encoding.as_str();
+        let lenstr = match &self.body {
+            _ if matches!(self.status, HttpStatus::NotModified) => String::new(),
+            HttpBody::Static(x) => format!("Content-Length: {}\r\n", x.len()),
+            HttpBody::Owned(x) => format!("Content-Length: {}\r\n", x.len()),
+            HttpBody::Generator(x) => match x.content_length() {
+                Some(len) => format!("Content-Length: {}\r\n", len),
+                None => "Transfer-Encoding: chunked\r\n".into(),
+            },
+        };
+        let chunked = lenstr.starts_with("Transfer-Encoding");
+
+        let mut buf: Vec<u8> = match self.status {
+            // a 101 has no body, the upgraded protocol starts right after it.
+            HttpStatus::SwitchingProtocols => format!("HTTP/1.1 {status}\r\n").into(),
+            _ => format!(
+                "HTTP/1.1 {status}\r\nContent-Type: {contentstr}\r\n{encodingstr}{lenstr}"
+            )
+            .into(),
+        };
+        for (name, value) in self.headers.iter() {
+            buf.extend_from_slice(format!("{name}: {value}\r\n").as_bytes());
+        }
+        let connection: &[u8] = match self.status {
+            HttpStatus::SwitchingProtocols => b"Upgrade: websocket\r\nConnection: Upgrade\r\n\r\n",
+            _ if keep_alive => b"Connection: keep-alive\r\n\r\n",
+            _ => b"Connection: close\r\n\r\n",
+        };
+        buf.extend_from_slice(connection);
+
+        HttpStream {
+            pending: buf,
+            pending_sent: 0,
+            body: self.body,
+            body_sent: 0,
+            chunked,
+            done: false,
+        }
+    }
+}
+
+// a response on its way out, written into the socket as room frees up.
+pub struct HttpStream {
+    // the header, then whatever the generator produced that didn't fit yet.
+    pending: Vec<u8>,
+    pending_sent: usize,
+    body: HttpBody,
+    body_sent: usize,
+    chunked: bool,
+    // the generator returned its last chunk.
+    done: bool,
+}
 
-    let contentstr = content_type.as_str();
-    let encodingstr = encoding_type.unwrap_or(HttpEncodingType::None).as_str();
-    format!("HTTP/1.1 200 OK\r\n{contentstr}{encodingstr}{lenstr}Connection: close\r\n\r\n").into()
+impl HttpStream {
+    // bytes that go out as they are, like websocket frames.
+    pub fn raw(data: Vec<u8>) -> Self {
+        HttpStream {
+            pending: data,
+            pending_sent: 0,
+            body: HttpBody::Owned(Vec::new()),
+            body_sent: 0,
+            chunked: false,
+            done: false,
+        }
+    }
+
+    // copies as much of the response as fits into `out`, returns how much was written.
+    pub fn write(&mut self, out: &mut [u8]) -> usize {
+        let mut written = 0;
+        while written < out.len() {
+            if self.pending_sent < self.pending.len() {
+                written += copy_from(&self.pending, &mut self.pending_sent, &mut out[written..]);
+                continue;
+            }
+
+            match &mut self.body {
+                HttpBody::Static(data) => {
+                    written += copy_from(data, &mut self.body_sent, &mut out[written..]);
+                    break;
+                }
+                HttpBody::Owned(data) => {
+                    written += copy_from(data, &mut self.body_sent, &mut out[written..]);
+                    break;
+                }
+                HttpBody::Generator(_) if self.done => break,
+                HttpBody::Generator(generator) => {
+                    // the last piece is out, it doesn't have to be held while the next is made.
+                    self.pending = Vec::new();
+                    self.pending_sent = 0;
+                    let mut data = Vec::new();
+                    let more = generator.fill(&mut data);
+                    if !self.chunked {
+                        self.pending = data;
+                    } else if !data.is_empty() {
+                        self.pending
+                            .extend_from_slice(format!("{:x}\r\n", data.len()).as_bytes());
+                        self.pending.extend_from_slice(&data);
+                        self.pending.extend_from_slice(b"\r\n");
+                    }
+                    if !more {
+                        if self.chunked {
+                            self.pending.extend_from_slice(b"0\r\n\r\n");
+                        }
+                        self.done = true;
+                    } else if self.pending.is_empty() {
+                        // nothing to send right now, ask again on the next poll.
+                        break;
+                    }
+                }
+            }
+        }
+        written
+    }
+
+    // the next write asks the generator for more, which takes heap.
+    pub fn generating(&self) -> bool {
+        let generator = matches!(self.body, HttpBody::Generator(_)) && !self.done;
+        generator && self.pending_sent == self.pending.len()
+    }
+
+    pub fn is_done(&self) -> bool {
+        let body_done = match &self.body {
+            HttpBody::Static(data) => self.body_sent == data.len(),
+            HttpBody::Owned(data) => self.body_sent == data.len(),
+            HttpBody::Generator(_) => self.done,
+        };
+        body_done && self.pending_sent == self.pending.len()
+    }
 }
 
-pub trait HttpCallback {
-    fn handle_request(&self, request: &HttpRequest) -> Vec<u8>;
+fn copy_from(src: &[u8], pos: &mut usize, out: &mut [u8]) -> usize {
+    let len = out.len().min(src.len() - *pos);
+    out[..len].copy_from_slice(&src[*pos..*pos + len]);
+    *pos += len;
+    len
+}
+
+impl From<HttpError> for HttpResponse {
+    fn from(value: HttpError) -> Self {
+        match value {
+            HttpError::Unsupported => {
+                HttpResponse::method_not_allowed(&SUPPORTED_METHODS.join(", "))
+            }
+            HttpError::TooLarge => HttpResponse::payload_too_large(),
+            HttpError::NotImplemented => HttpResponse::error(HttpStatus::NotImplemented),
+            HttpError::ParseError | HttpError::Incomplete => HttpResponse::bad_request(),
+        }
+    }
+}
+
+struct Route {
+    method: &'static str,
+    pattern: &'static str,
+    handler: RouteHandler,
+    // reachable without authentication.
+    public: bool,
+}
+
+// maps (method, path pattern) to handlers. a `{name}` segment in a pattern matches any
+// single path segment, handlers read it back with `HttpRequest::param`.
+#[derive(Default)]
+pub struct Router {
+    routes: Vec<Route>,
+    // called for paths no route matches, instead of answering 404.
+    fallback: Option<RouteHandler>,
+}
+
+impl Router {
+    // routes need authentication, once a token is set.
+    pub fn route(&mut self, method: &'static str, pattern: &'static str, handler: RouteHandler) {
+        self.routes.push(Route {
+            method,
+            pattern,
+            handler,
+            public: false,
+        });
+    }
+
+    // for read-only routes, anyone may use these.
+    pub fn public_route(
+        &mut self,
+        method: &'static str,
+        pattern: &'static str,
+        handler: RouteHandler,
+    ) {
+        self.routes.push(Route {
+            method,
+            pattern,
+            handler,
+            public: true,
+        });
+    }
+
+    pub fn fallback(&mut self, handler: RouteHandler) {
+        self.fallback = Some(handler);
+    }
+
+    // the fallback is public.
+    fn dispatch(
+        &self,
+        mut request: HttpRequest,
+        auth: &mut Auth,
+        peer: Option<IpAddress>,
+    ) -> Result<HttpResponse, HttpError> {
+        let mut allowed = Vec::<&str>::new();
+        for route in self.routes.iter() {
+            let Some(params) = match_path(route.pattern, &request.path) else {
+                continue;
+            };
+            if route.method == request.method {
+                if !route.public {
+                    if let Err(response) = auth.check(&request, peer) {
+                        return Ok(response);
+                    }
+                }
+                request.params = params;
+                return (route.handler)(&request);
+            }
+            if !allowed.contains(&route.method) {
+                allowed.push(route.method);
+            }
+        }
+
+        if allowed.is_empty() {
+            return match self.fallback {
+                Some(handler) => handler(&request),
+                None => Ok(HttpResponse::not_found()),
+            };
+        }
+        Ok(HttpResponse::method_not_allowed(&allowed.join(", ")))
+    }
+}
+
+// the path parameters if `path` matches `pattern`.
+fn match_path(pattern: &str, path: &str) -> Option<Vec<(String, String)>> {
+    let mut params = Vec::new();
+    let mut segments = path.split('/');
+    for expected in pattern.split('/') {
+        let segment = segments.next()?;
+        match expected.strip_prefix('{').and_then(|x| x.strip_suffix('}')) {
+            Some(name) if !segment.is_empty() => params.push((name.into(), segment.into())),
+            Some(_) => return None,
+            None if expected == segment => {}
+            None => return None,
+        }
+    }
+    segments.next().is_none().then_some(params)
+}
+
+// protected routes take `Authorization: Bearer <token>`, or basic auth as admin:<token>.
+// without a token every route is open.
+#[derive(Default)]
+struct Auth {
+    token: Option<String>,
+    // the whole Authorization header basic auth has to send.
+    basic: String,
+    failures: Vec<AuthFailures>,
+}
+
+// a peer's failed attempts, so one client guessing doesn't lock out the others.
+struct AuthFailures {
+    peer: Option<IpAddress>,
+    count: u32,
+    last: u64,
+}
+
+impl Auth {
+    fn check(
+        &mut self,
+        request: &HttpRequest,
+        peer: Option<IpAddress>,
+    ) -> Result<(), HttpResponse> {
+        let Some(token) = &self.token else {
+            return Ok(());
+        };
+        let now = get_uptime();
+        self.failures.retain(|x| now - x.last < AUTH_LOCKOUT_MS);
+        let entry = self.failures.iter().position(|x| x.peer == peer);
+        if let Some(x) = entry
+            .map(|i| &self.failures[i])
+            .filter(|x| x.count >= AUTH_MAX_FAILURES)
+        {
+            let retry = format!("{}", (x.last + AUTH_LOCKOUT_MS - now) / 1000 + 1);
+            return Err(
+                HttpResponse::error(HttpStatus::TooManyRequests).with_header("Retry-After", retry)
+            );
+        }
+
+        let given = request.header("authorization").unwrap_or_default().trim();
+        let bearer = given.strip_prefix("Bearer ").unwrap_or_default();
+        // browsers can't set headers on a websocket, the upgrade may carry `?token=` instead.
+        let query = request
+            .query_param("token")
+            .filter(|_| {
+                request
+                    .header("upgrade")
+                    .is_some_and(|x| x.eq_ignore_ascii_case("websocket"))
+            })
+            .unwrap_or_default();
+        if constant_time_eq(bearer, token)
+            || constant_time_eq(given, &self.basic)
+            || constant_time_eq(query, token)
+        {
+            if let Some(i) = entry {
+                self.failures.swap_remove(i);
+            }
+            return Ok(());
+        }
+        // browsers ask without credentials first, only wrong ones count.
+        if !given.is_empty() || !query.is_empty() {
+            warn!("authentication failed");
+            match entry {
+                Some(i) => {
+                    self.failures[i].count += 1;
+                    self.failures[i].last = now;
+                }
+                None => {
+                    if self.failures.len() == AUTH_MAX_PEERS {
+                        let oldest = (0..AUTH_MAX_PEERS).min_by_key(|&i| self.failures[i].last);
+                        self.failures.swap_remove(oldest.unwrap());
+                    }
+                    self.failures.push(AuthFailures {
+                        peer,
+                        count: 1,
+                        last: now,
+                    });
+                }
+            }
+        }
+        Err(HttpResponse::error(HttpStatus::Unauthorized)
+            .with_header("WWW-Authenticate", "Basic realm=\"stamdev\"".into()))
+    }
+}
+
+// doesn't give away how much of the token was right through its timing.
+fn constant_time_eq(a: &str, b: &str) -> bool {
+    a.len() == b.len() && a.bytes().zip(b.bytes()).fold(0, |acc, (x, y)| acc | (x ^ y)) == 0
 }
 
 pub struct Httpserver {
-    callbacks: CallbackBt,
+    router: Router,
+    auth: Auth,
+}
+
+pub struct HttpReply {
+    pub stream: HttpStream,
+    // the connection stays open for the next request.
+    pub keep_alive: bool,
+    pub upgrade: Option<Box<dyn WebSocketHandler>>,
+    // the response never ends, the connection stays busy until the client goes away.
+    pub long_lived: Option<LongLived>,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum LongLived {
+    EventStream,
+    WebSocket,
 }
 
+#[allow(dead_code)]
 pub struct HttpRequest {
     pub method: String,
-    pub path: String,
+    pub path: String, // percent-decoded, without the query string.
+    pub query: Vec<(String, String)>,
+    pub headers: Vec<(String, String)>, // names are lowercase.
+    pub params: Vec<(String, String)>,  // filled in by the router.
     pub body: String,
+    // whether the client wants the connection kept open after this request.
+    pub keep_alive: bool,
 }
 
-pub const HTTP_404_RESPONSE: &[u8] = "HTTP/1.1 404 Not Found\r\n\
-                                Content-Type: text/plain\r\n\
-                                Content-Length: 13\r\n\
-                                Connection: close\r\n\r\n\
-                                404 Not Found"
-    .as_bytes();
+impl HttpRequest {
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(key, _)| key.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str())
+    }
 
-impl Httpserver {
-    pub fn new(callbacks: CallbackBt) -> Self {
-        Httpserver { callbacks }
+    pub fn json(&self) -> Result<JsonValue, HttpError> {
+        JsonValue::parse(&self.body).ok_or(HttpError::ParseError)
+    }
+
+    pub fn param(&self, name: &str) -> Option<&str> {
+        self.params
+            .iter()
+            .find(|(key, _)| key == name)
+            .map(|(_, value)| value.as_str())
     }
 
-    pub fn parse_request(&mut self, request_buf: &[u8]) -> Result<Vec<u8>, HttpError> {
-        let req = String::from_utf8(request_buf.to_vec())?;
-        // For simplicity, assume that the request is well-formed
-        let parts: Vec<&str> = req.lines().collect();
+    #[allow(dead_code)]
+    pub fn query_param(&self, name: &str) -> Option<&str> {
+        self.query
+            .iter()
+            .find(|(key, _)| key == name)
+            .map(|(_, value)| value.as_str())
+    }
 
-        let method: String = parts[0].split_whitespace().nth(0).unwrap_or("").into();
+    // parses everything up to the empty line, the body is filled in once it arrived.
+    fn parse_head(head: &[u8]) -> Result<Self, HttpError> {
+        let head = core::str::from_utf8(head).map_err(|_| HttpError::ParseError)?;
+        // split at the lf, a bare one is taken as a line end too (RFC 9112 2.2).
+        let mut lines = head.split('\n').map(|x| x.strip_suffix('\r').unwrap_or(x));
 
-        if !SUPPORTED_METHODS.iter().any(|x| x == &method.as_str()) {
+        let mut requestline = lines.next().unwrap_or("").split(' ');
+        let (Some(method), Some(target), Some(version), None) = (
+            requestline.next(),
+            requestline.next(),
+            requestline.next(),
+            requestline.next(),
+        ) else {
+            return Err(HttpError::ParseError);
+        };
+        if !version.starts_with("HTTP/1.") || !target.starts_with('/') {
+            return Err(HttpError::ParseError);
+        }
+        if !SUPPORTED_METHODS.contains(&method) {
             return Err(HttpError::Unsupported);
         }
 
-        let path = parts[0].split_whitespace().nth(1).unwrap().into();
+        let (path, query) = target.split_once('?').unwrap_or((target, ""));
+        let query = query
+            .split('&')
+            .filter(|x| !x.is_empty())
+            .map(|x| {
+                let (key, value) = x.split_once('=').unwrap_or((x, ""));
+                Ok((percent_decode(key, true)?, percent_decode(value, true)?))
+            })
+            .collect::<Result<Vec<_>, HttpError>>()?;
 
-        let body_index = req.find("\r\n\r\n").ok_or(HttpError::ParseError)?;
-        let body: &str = req.get(body_index + 4..).unwrap_or("");
+        let headers = lines
+            .map(|x| {
+                let (name, value) = x.split_once(':').ok_or(HttpError::ParseError)?;
+                if name.is_empty() || name.ends_with(' ') {
+                    return Err(HttpError::ParseError);
+                }
+                Ok((name.to_ascii_lowercase(), value.trim().into()))
+            })
+            .collect::<Result<Vec<(String, String)>, HttpError>>()?;
 
-        let request = HttpRequest {
-            method,
-            path,
-            body: body.into(),
+        // HTTP/1.1 connections are persistent unless closed, 1.0 ones only if asked (RFC 9112 9.3).
+        let connection = |option: &str| {
+            headers
+                .iter()
+                .filter(|(name, _)| name == "connection")
+                .flat_map(|(_, value)| value.split(','))
+                .any(|x| x.trim().eq_ignore_ascii_case(option))
         };
+        let keep_alive = match (connection("close"), connection("keep-alive")) {
+            (true, _) => false,
+            (_, true) => true,
+            _ => version == "HTTP/1.1",
+        };
+
+        Ok(HttpRequest {
+            keep_alive,
+            method: method.into(),
+            path: percent_decode(path, false)?,
+            query,
+            headers,
+            params: Vec::new(),
+            body: String::new(),
+        })
+    }
+
+    fn content_length(&self) -> Result<usize, HttpError> {
+        // we don't take chunked bodies, and without a length there is no body (RFC 9112 6.3).
+        if self.header("transfer-encoding").is_some() {
+            return Err(HttpError::NotImplemented);
+        }
+        match self.header("content-length") {
+            Some(len) => len.parse().map_err(|_| HttpError::ParseError),
+            None => Ok(0),
+        }
+    }
+}
 
-        let callback = match request.method.as_str() {
-            "GET" => self.callbacks[0],
-            "POST" => self.callbacks[1],
-            _ => return Err(HttpError::CallbackNotFound),
+fn percent_decode(input: &str, plus_as_space: bool) -> Result<String, HttpError> {
+    let mut out = Vec::<u8>::with_capacity(input.len());
+    let mut bytes = input.bytes();
+    while let Some(byte) = bytes.next() {
+        match byte {
+            b'%' => {
+                let hex = [bytes.next().unwrap_or(0), bytes.next().unwrap_or(0)];
+                let hex = core::str::from_utf8(&hex).map_err(|_| HttpError::ParseError)?;
+                out.push(u8::from_str_radix(hex, 16).map_err(|_| HttpError::ParseError)?);
+            }
+            b'+' if plus_as_space => out.push(b' '),
+            _ => out.push(byte),
+        }
+    }
+    Ok(String::from_utf8(out)?)
+}
+
+fn find(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack.windows(needle.len()).position(|x| x == needle)
+}
+
+// collects a request from however many tcp segments it arrives in.
+#[derive(Default)]
+pub struct HttpParser {
+    buf: Vec<u8>,
+    // the parsed head, and where its body starts and ends in buf.
+    head: Option<(HttpRequest, usize, usize)>,
+    // the last request was followed by the start of another one.
+    pipelined: bool,
+}
+
+impl HttpParser {
+    // returns the request once all of it is in, anything after it is kept for the next one.
+    pub fn push(&mut self, data: &[u8]) -> Result<HttpRequest, HttpError> {
+        // grown by what came in only, so a request takes no more heap than its size.
+        self.buf.reserve_exact(data.len());
+        self.buf.extend_from_slice(data);
+        self.pipelined = false;
+        let res = self.parse();
+        if matches!(res, Err(ref x) if !matches!(x, HttpError::Incomplete)) {
+            self.reset();
+        }
+        res
+    }
+
+    pub fn reset(&mut self) {
+        self.buf.clear();
+        self.head = None;
+        self.pipelined = false;
+    }
+
+    // bytes of a pipelined request are waiting, even if nothing new was received.
+    pub fn pending(&self) -> bool {
+        self.pipelined
+    }
+
+    fn parse(&mut self) -> Result<HttpRequest, HttpError> {
+        if self.head.is_none() {
+            let Some(end) = find(&self.buf, b"\r\n\r\n") else {
+                return Err(if self.buf.len() > HTTP_MAX_HEAD {
+                    HttpError::TooLarge
+                } else {
+                    HttpError::Incomplete
+                });
+            };
+            let request = HttpRequest::parse_head(&self.buf[..end])?;
+            let len = request.content_length()?;
+            if len > HTTP_MAX_BODY {
+                return Err(HttpError::TooLarge);
+            }
+            self.head = Some((request, end + 4, end + 4 + len));
+        }
+
+        let Some((_, start, end)) = self.head else {
+            return Err(HttpError::Incomplete);
+        };
+        if self.buf.len() < end {
+            return Err(HttpError::Incomplete);
+        }
+
+        let (mut request, ..) = self.head.take().unwrap();
+        request.body = String::from_utf8(self.buf[start..end].to_vec())?;
+        self.buf.drain(..end);
+        self.pipelined = !self.buf.is_empty();
+        Ok(request)
+    }
+}
+
+impl Httpserver {
+    pub fn new(router: Router) -> Self {
+        Httpserver {
+            router,
+            auth: Auth::default(),
+        }
+    }
+
+    // an empty token turns authentication off.
+    pub fn set_token(&mut self, token: &str) {
+        self.auth.token = (!token.is_empty()).then(|| token.into());
+        self.auth.basic = format!("Basic {}", base64(format!("{AUTH_USER}:{token}").as_bytes()));
+    }
+
+    // feeds received bytes to a connection's parser, a reply is returned once a request is in.
+    // requests that fail to parse or to be handled are answered with an error status.
+    // failed authentication is counted against `peer`.
+    pub fn parse_request(
+        &mut self,
+        parser: &mut HttpParser,
+        request_buf: &[u8],
+        peer: Option<IpAddress>,
+    ) -> Option<HttpReply> {
+        let (resp, keep_alive) = match parser.push(request_buf) {
+            Err(HttpError::Incomplete) => return None,
+            Err(x) => (Err(x), false),
+            Ok(request) => {
+                let keep_alive = request.keep_alive;
+                (self.router.dispatch(request, &mut self.auth, peer), keep_alive)
+            }
         };
+        // after an error we can't tell where the next request starts.
+        let keep_alive = keep_alive && resp.is_ok();
+        let mut resp = resp.unwrap_or_else(|x| {
+            warn!("failed to handle request: {}", x);
+            x.into()
+        });
+        let upgrade = resp.upgrade.take();
+        let long_lived = match resp.content_type {
+            _ if upgrade.is_some() => Some(LongLived::WebSocket),
+            HttpContentType::EventStream => Some(LongLived::EventStream),
+            _ => None,
+        };
+        Some(HttpReply {
+            stream: resp.into_stream(keep_alive),
+            keep_alive: keep_alive || upgrade.is_some(),
+            upgrade,
+            long_lived,
+        })
+    }
+
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-        let resp = callback.handle_request(&request);
-        Ok(resp) //TODO: this assumes callbacks can never fail!
+    // pushes `data` the way it comes off a socket, `chunk` bytes at a time.
+    fn push_in_chunks(parser: &mut HttpParser, data: &[u8], chunk: usize) -> Vec<HttpRequest> {
+        let mut requests = Vec::new();
+        for part in data.chunks(chunk) {
+            let mut part = part;
+            // like run_webserver, a pipelined request is looked at again with nothing new.
+            loop {
+                match parser.push(part) {
+                    Ok(request) => requests.push(request),
+                    Err(HttpError::Incomplete) => {}
+                    Err(x) => panic!("{x:?}"),
+                }
+                if !parser.pending() {
+                    break;
+                }
+                part = &[];
+            }
+        }
+        requests
+    }
+
+    #[test]
+    fn post_split_across_pushes() {
+        let body = "x".repeat(200);
+        let data = format!(
+            "POST /rgb?a=1&b=%23ff HTTP/1.1\r\nHost: stamdev.lan\r\nContent-Length: {}\r\n\
+             X-Padding: {}\r\n\r\n{body}",
+            body.len(),
+            "p".repeat(100),
+        );
+        let mut parser = HttpParser::default();
+        let requests = push_in_chunks(&mut parser, data.as_bytes(), 128);
+        assert_eq!(requests.len(), 1);
+        let request = &requests[0];
+        assert_eq!(request.method, "POST");
+        assert_eq!(request.path, "/rgb");
+        assert_eq!(request.query_param("b"), Some("#ff"));
+        assert_eq!(request.header("host"), Some("stamdev.lan"));
+        assert_eq!(request.body, body);
+        assert!(request.keep_alive);
+        assert!(!parser.pending());
+    }
+
+    #[test]
+    fn pipelined_requests() {
+        let data = b"GET /a HTTP/1.1\r\n\r\n\
+                     PUT /b HTTP/1.1\r\nContent-Length: 2\r\n\r\nhi\
+                     GET /c HTTP/1.0\r\n\r\n";
+        let mut parser = HttpParser::default();
+        let requests = push_in_chunks(&mut parser, data, data.len());
+        let paths: Vec<_> = requests.iter().map(|x| x.path.as_str()).collect();
+        assert_eq!(paths, ["/a", "/b", "/c"]);
+        assert_eq!(requests[1].body, "hi");
+        // 1.0 connections close unless asked not to.
+        assert!(!requests[2].keep_alive);
+
+        // the start of the next one waits for the rest.
+        let mut parser = HttpParser::default();
+        assert_eq!(
+            push_in_chunks(&mut parser, b"GET /a HTTP/1.1\r\n\r\nGET /b", 64).len(),
+            1
+        );
+        assert!(matches!(parser.push(b" HTTP/1.1\r\n\r\n"), Ok(x) if x.path == "/b"));
+    }
+
+    #[test]
+    fn oversize_head_and_body() {
+        let mut parser = HttpParser::default();
+        let head = format!("GET / HTTP/1.1\r\nX-Padding: {}", "p".repeat(HTTP_MAX_HEAD));
+        assert!(matches!(
+            parser.push(head.as_bytes()),
+            Err(HttpError::TooLarge)
+        ));
+        // the connection can't be read any further, what was buffered is gone.
+        assert!(parser.buf.is_empty());
+
+        let head = format!(
+            "PUT / HTTP/1.1\r\nContent-Length: {}\r\n\r\n",
+            HTTP_MAX_BODY + 1
+        );
+        assert!(matches!(
+            parser.push(head.as_bytes()),
+            Err(HttpError::TooLarge)
+        ));
+    }
+
+    #[test]
+    fn malformed_request_line() {
+        for data in [
+            "GET\r\n\r\n",
+            "GET / HTTP/1.1 extra\r\n\r\n",
+            "GET nopath HTTP/1.1\r\n\r\n",
+            "GET / SPDY/3\r\n\r\n",
+            "GET /%zz HTTP/1.1\r\n\r\n",
+            "GET / HTTP/1.1\r\nno colon\r\n\r\n",
+            "GET / HTTP/1.1\r\nContent-Length: ten\r\n\r\n",
+        ] {
+            let mut parser = HttpParser::default();
+            assert!(
+                matches!(parser.push(data.as_bytes()), Err(HttpError::ParseError)),
+                "{data}"
+            );
+        }
+        let mut parser = HttpParser::default();
+        assert!(matches!(
+            parser.push(b"DELETE / HTTP/1.1\r\n\r\n"),
+            Err(HttpError::Unsupported)
+        ));
+        let chunked = b"POST / HTTP/1.1\r\nTransfer-Encoding: chunked\r\n\r\n";
+        assert!(matches!(
+            parser.push(chunked),
+            Err(HttpError::NotImplemented)
+        ));
     }
 }
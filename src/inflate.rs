@@ -0,0 +1,358 @@
+//inflate
+//a small deflate (RFC 1951) decoder for the gzipped assets, after zlib's puff.
+//build.rs full flushes the stream every few KB, no back reference crosses a flush, so the
+//stream is inflated one segment at a time and the segment itself serves as the window.
+extern crate alloc;
+use alloc::vec::Vec;
+
+const MAXBITS: usize = 15;
+const MAXLCODES: usize = 286;
+const MAXDCODES: usize = 30;
+const FIXLCODES: usize = 288;
+
+const LEN_BASE: [u16; 29] = [
+    3, 4, 5, 6, 7, 8, 9, 10, 11, 13, 15, 17, 19, 23, 27, 31, 35, 43, 51, 59, 67, 83, 99, 115, 131,
+    163, 195, 227, 258,
+];
+const LEN_EXTRA: [u8; 29] = [
+    0, 0, 0, 0, 0, 0, 0, 0, 1, 1, 1, 1, 2, 2, 2, 2, 3, 3, 3, 3, 4, 4, 4, 4, 5, 5, 5, 5, 0,
+];
+const DIST_BASE: [u16; 30] = [
+    1, 2, 3, 4, 5, 7, 9, 13, 17, 25, 33, 49, 65, 97, 129, 193, 257, 385, 513, 769, 1025, 1537,
+    2049, 3073, 4097, 6145, 8193, 12289, 16385, 24577,
+];
+const DIST_EXTRA: [u8; 30] = [
+    0, 0, 0, 0, 1, 1, 2, 2, 3, 3, 4, 4, 5, 5, 6, 6, 7, 7, 8, 8, 9, 9, 10, 10, 11, 11, 12, 12, 13,
+    13,
+];
+// order the code length code lengths are sent in.
+const CLEN_ORDER: [usize; 19] = [
+    16, 17, 18, 0, 8, 7, 9, 6, 10, 5, 11, 4, 12, 3, 13, 2, 14, 1, 15,
+];
+
+#[derive(Debug, defmt::Format)]
+pub enum InflateError {
+    BadHeader,
+    Corrupt,
+}
+
+// canonical huffman code, as symbol counts per length and symbols ordered by code.
+struct Huffman {
+    count: [u16; MAXBITS + 1],
+    symbol: [u16; FIXLCODES],
+}
+
+impl Huffman {
+    fn new(lengths: &[u8]) -> Result<Self, InflateError> {
+        let mut huffman = Huffman {
+            count: [0; MAXBITS + 1],
+            symbol: [0; FIXLCODES],
+        };
+        for len in lengths {
+            huffman.count[*len as usize] += 1;
+        }
+        // an over-subscribed set of lengths isn't a code.
+        let mut left = 1i32;
+        for len in 1..=MAXBITS {
+            left = (left << 1) - huffman.count[len] as i32;
+            if left < 0 {
+                return Err(InflateError::Corrupt);
+            }
+        }
+
+        let mut offsets = [0u16; MAXBITS + 1];
+        for len in 1..MAXBITS {
+            offsets[len + 1] = offsets[len] + huffman.count[len];
+        }
+        for (symbol, len) in lengths.iter().enumerate().filter(|(_, x)| **x != 0) {
+            huffman.symbol[offsets[*len as usize] as usize] = symbol as u16;
+            offsets[*len as usize] += 1;
+        }
+        Ok(huffman)
+    }
+}
+
+struct Bits {
+    data: &'static [u8],
+    pos: usize,
+    buf: u32,
+    cnt: u32,
+}
+
+impl Bits {
+    fn bits(&mut self, need: u32) -> Result<u32, InflateError> {
+        let mut val = self.buf;
+        while self.cnt < need {
+            let byte = *self.data.get(self.pos).ok_or(InflateError::Corrupt)?;
+            self.pos += 1;
+            val |= (byte as u32) << self.cnt;
+            self.cnt += 8;
+        }
+        self.buf = val >> need;
+        self.cnt -= need;
+        Ok(val & ((1 << need) - 1))
+    }
+
+    fn decode(&mut self, huffman: &Huffman) -> Result<usize, InflateError> {
+        let (mut code, mut first, mut index) = (0i32, 0i32, 0i32);
+        for len in 1..=MAXBITS {
+            code |= self.bits(1)? as i32;
+            let count = huffman.count[len] as i32;
+            if code - count < first {
+                return Ok(huffman.symbol[(index + code - first) as usize] as usize);
+            }
+            index += count;
+            first = (first + count) << 1;
+            code <<= 1;
+        }
+        Err(InflateError::Corrupt)
+    }
+}
+
+// inflates a gzip member stored in flash.
+pub struct Inflater {
+    bits: Bits,
+    len: usize,
+    done: bool,
+}
+
+impl Inflater {
+    pub fn new(gzip: &'static [u8]) -> Result<Self, InflateError> {
+        // build.rs writes no optional header fields.
+        if gzip.len() < 18 || gzip[..4] != [0x1f, 0x8b, 8, 0] {
+            return Err(InflateError::BadHeader);
+        }
+        let (data, trailer) = gzip[10..].split_at(gzip.len() - 18);
+        Ok(Inflater {
+            bits: Bits {
+                data,
+                pos: 0,
+                buf: 0,
+                cnt: 0,
+            },
+            len: u32::from_le_bytes(trailer[4..].try_into().unwrap()) as usize,
+            done: false,
+        })
+    }
+
+    // the size of the inflated content, from the gzip trailer.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    // appends the next segment to `out`, returns false once the stream ended.
+    pub fn segment(&mut self, out: &mut Vec<u8>) -> Result<bool, InflateError> {
+        let start = out.len();
+        while !self.done {
+            self.done = self.bits.bits(1)? == 1;
+            match self.bits.bits(2)? {
+                0 => {
+                    // a flush ends in an empty stored block.
+                    if self.stored(out)? == 0 && !self.done {
+                        return Ok(true);
+                    }
+                }
+                1 => self.fixed(out, start)?,
+                2 => self.dynamic(out, start)?,
+                _ => return Err(InflateError::Corrupt),
+            }
+        }
+        Ok(false)
+    }
+
+    fn stored(&mut self, out: &mut Vec<u8>) -> Result<usize, InflateError> {
+        let bits = &mut self.bits;
+        bits.buf = 0;
+        bits.cnt = 0;
+        let header = bits
+            .data
+            .get(bits.pos..bits.pos + 4)
+            .ok_or(InflateError::Corrupt)?;
+        let len = u16::from_le_bytes([header[0], header[1]]);
+        if len != !u16::from_le_bytes([header[2], header[3]]) {
+            return Err(InflateError::Corrupt);
+        }
+        bits.pos += 4;
+        let data = bits
+            .data
+            .get(bits.pos..bits.pos + len as usize)
+            .ok_or(InflateError::Corrupt)?;
+        out.extend_from_slice(data);
+        bits.pos += len as usize;
+        Ok(len as usize)
+    }
+
+    fn fixed(&mut self, out: &mut Vec<u8>, start: usize) -> Result<(), InflateError> {
+        let mut lengths = [8u8; FIXLCODES];
+        lengths[144..256].fill(9);
+        lengths[256..280].fill(7);
+        let lencode = Huffman::new(&lengths)?;
+        let distcode = Huffman::new(&[5; MAXDCODES])?;
+        self.codes(out, start, &lencode, &distcode)
+    }
+
+    fn dynamic(&mut self, out: &mut Vec<u8>, start: usize) -> Result<(), InflateError> {
+        let nlen = self.bits.bits(5)? as usize + 257;
+        let ndist = self.bits.bits(5)? as usize + 1;
+        let ncode = self.bits.bits(4)? as usize + 4;
+        if nlen > MAXLCODES || ndist > MAXDCODES {
+            return Err(InflateError::Corrupt);
+        }
+
+        let mut lengths = [0u8; MAXLCODES + MAXDCODES];
+        for index in CLEN_ORDER.iter().take(ncode) {
+            lengths[*index] = self.bits.bits(3)? as u8;
+        }
+        let lencode = Huffman::new(&lengths[..19])?;
+        let mut index = 0;
+        while index < nlen + ndist {
+            let (len, repeat) = match self.bits.decode(&lencode)? {
+                x @ 0..=15 => (x as u8, 1),
+                16 if index == 0 => return Err(InflateError::Corrupt),
+                16 => (lengths[index - 1], 3 + self.bits.bits(2)?),
+                17 => (0, 3 + self.bits.bits(3)?),
+                _ => (0, 11 + self.bits.bits(7)?),
+            };
+            let end = index + repeat as usize;
+            lengths
+                .get_mut(index..end)
+                .filter(|_| end <= nlen + ndist)
+                .ok_or(InflateError::Corrupt)?
+                .fill(len);
+            index = end;
+        }
+        // without an end of block code the block never ends.
+        if lengths[256] == 0 {
+            return Err(InflateError::Corrupt);
+        }
+
+        let lencode = Huffman::new(&lengths[..nlen])?;
+        let distcode = Huffman::new(&lengths[nlen..nlen + ndist])?;
+        self.codes(out, start, &lencode, &distcode)
+    }
+
+    fn codes(
+        &mut self,
+        out: &mut Vec<u8>,
+        start: usize,
+        lencode: &Huffman,
+        distcode: &Huffman,
+    ) -> Result<(), InflateError> {
+        loop {
+            let symbol = self.bits.decode(lencode)?;
+            if symbol < 256 {
+                out.push(symbol as u8);
+                continue;
+            }
+            if symbol == 256 {
+                return Ok(());
+            }
+
+            let symbol = symbol - 257;
+            if symbol >= LEN_BASE.len() {
+                return Err(InflateError::Corrupt);
+            }
+            let len =
+                LEN_BASE[symbol] as usize + self.bits.bits(LEN_EXTRA[symbol] as u32)? as usize;
+            let symbol = self.bits.decode(distcode)?;
+            if symbol >= DIST_BASE.len() {
+                return Err(InflateError::Corrupt);
+            }
+            let dist =
+                DIST_BASE[symbol] as usize + self.bits.bits(DIST_EXTRA[symbol] as u32)? as usize;
+            // only this segment is around to copy from.
+            if dist > out.len() - start {
+                return Err(InflateError::Corrupt);
+            }
+            for _ in 0..len {
+                out.push(out[out.len() - dist]);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // gzip members as zlib writes them, one per block type.
+    const STORED: [u8; 30] = [
+        0x1f, 0x8b, 0x08, 0x00, 0x00, 0x00, 0x00, 0x00, 0x04, 0x03, 0x01, 0x07, 0x00, 0xf8, 0xff,
+        0x73, 0x74, 0x61, 0x6d, 0x64, 0x65, 0x76, 0x2b, 0x03, 0x73, 0x53, 0x07, 0x00, 0x00, 0x00,
+    ];
+    // the repeats are one back reference that overlaps what it copies.
+    const FIXED: [u8; 28] = [
+        0x1f, 0x8b, 0x08, 0x00, 0x00, 0x00, 0x00, 0x00, 0x02, 0x03, 0xcb, 0x48, 0xcd, 0xc9, 0xc9,
+        0x57, 0xc8, 0x40, 0x27, 0x01, 0xe3, 0x51, 0x3d, 0x8d, 0x17, 0x00, 0x00, 0x00,
+    ];
+    const DYNAMIC: [u8; 57] = [
+        0x1f, 0x8b, 0x08, 0x00, 0x00, 0x00, 0x00, 0x00, 0x02, 0x03, 0x05, 0xc1, 0x09, 0x01, 0x00,
+        0x20, 0x08, 0x03, 0xc0, 0x4a, 0x3c, 0x32, 0x58, 0x1c, 0x04, 0xfb, 0x57, 0xf0, 0xae, 0x49,
+        0xfa, 0x73, 0x9c, 0x14, 0x94, 0xa2, 0x6f, 0xfb, 0xb3, 0x48, 0xcd, 0x0a, 0x19, 0xc3, 0x70,
+        0x56, 0xb6, 0xb8, 0x1f, 0xa4, 0x37, 0xab, 0x10, 0x28, 0x00, 0x00, 0x00,
+    ];
+    // "hello hello hello hello", full flushed after the first 12 bytes like build.rs does.
+    const FLUSHED: [u8; 43] = [
+        0x1f, 0x8b, 0x08, 0x00, 0x00, 0x00, 0x00, 0x00, 0x02, 0x03, 0xca, 0x48, 0xcd, 0xc9, 0xc9,
+        0x57, 0xc8, 0x00, 0x93, 0x00, 0x00, 0x00, 0x00, 0xff, 0xff, 0xcb, 0x48, 0xcd, 0xc9, 0xc9,
+        0x57, 0xc8, 0x00, 0x91, 0x00, 0xe3, 0x51, 0x3d, 0x8d, 0x17, 0x00, 0x00, 0x00,
+    ];
+
+    fn inflate(gzip: &'static [u8]) -> Result<Vec<u8>, InflateError> {
+        let mut inflater = Inflater::new(gzip)?;
+        let mut out = Vec::new();
+        while inflater.segment(&mut out)? {}
+        assert_eq!(out.len(), inflater.len());
+        Ok(out)
+    }
+
+    #[test]
+    fn block_types() {
+        assert_eq!(inflate(&STORED).unwrap(), b"stamdev");
+        assert_eq!(inflate(&FIXED).unwrap(), b"hello hello hello hello");
+        assert_eq!(
+            inflate(&DYNAMIC).unwrap(),
+            b"a9993e364706816aba3e25717850c26c9cd0d89d"
+        );
+    }
+
+    #[test]
+    fn one_segment_per_flush() {
+        let mut inflater = Inflater::new(&FLUSHED).unwrap();
+        let mut out = Vec::new();
+        assert!(inflater.segment(&mut out).unwrap());
+        assert_eq!(out, b"hello hello ");
+        // the next segment only refers back into itself.
+        assert!(!inflater.segment(&mut out).unwrap());
+        assert_eq!(out, b"hello hello hello hello");
+        assert!(!inflater.segment(&mut out).unwrap());
+    }
+
+    #[test]
+    fn broken_streams() {
+        assert!(matches!(
+            Inflater::new(&[0x50; 18]),
+            Err(InflateError::BadHeader)
+        ));
+        assert!(matches!(
+            Inflater::new(&STORED[..17]),
+            Err(InflateError::BadHeader)
+        ));
+
+        // the stored block's length and its complement disagree.
+        let mut bad_len = STORED.to_vec();
+        bad_len[13] = 0;
+        assert!(matches!(
+            inflate(bad_len.leak()),
+            Err(InflateError::Corrupt)
+        ));
+
+        // block type 3 doesn't exist.
+        let mut reserved = FIXED.to_vec();
+        reserved[10] |= 0b110;
+        assert!(matches!(
+            inflate(reserved.leak()),
+            Err(InflateError::Corrupt)
+        ));
+    }
+}
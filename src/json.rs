@@ -0,0 +1,290 @@
+//json
+//just enough of RFC 8259 for the rest api. numbers are integers, except for Hundredths
+//which is only ever written, so no float formatting or parsing gets linked in.
+extern crate alloc;
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::fmt;
+
+// nesting deeper than this is refused, the parser recurses on the stack.
+const MAX_DEPTH: usize = 8;
+
+pub enum JsonValue {
+    Null,
+    Bool(bool),
+    Number(i32),
+    // a number with two decimals, stored times 100.
+    Hundredths(i32),
+    String(String),
+    Array(Vec<JsonValue>),
+    Object(Vec<(String, JsonValue)>),
+}
+
+impl JsonValue {
+    pub fn parse(input: &str) -> Option<JsonValue> {
+        let mut parser = Parser {
+            input: input.as_bytes(),
+            pos: 0,
+        };
+        let value = parser.value(0)?;
+        parser.skip_whitespace();
+        (parser.pos == parser.input.len()).then_some(value)
+    }
+
+    pub fn object(members: Vec<(&str, JsonValue)>) -> JsonValue {
+        JsonValue::Object(members.into_iter().map(|(k, v)| (k.into(), v)).collect())
+    }
+
+    pub fn get(&self, key: &str) -> Option<&JsonValue> {
+        match self {
+            JsonValue::Object(x) => x.iter().find(|(k, _)| k == key).map(|(_, v)| v),
+            _ => None,
+        }
+    }
+
+    pub fn as_i32(&self) -> Option<i32> {
+        match self {
+            JsonValue::Number(x) => Some(*x),
+            _ => None,
+        }
+    }
+}
+
+impl From<bool> for JsonValue {
+    fn from(value: bool) -> Self {
+        JsonValue::Bool(value)
+    }
+}
+
+impl From<i32> for JsonValue {
+    fn from(value: i32) -> Self {
+        JsonValue::Number(value)
+    }
+}
+
+impl From<u8> for JsonValue {
+    fn from(value: u8) -> Self {
+        JsonValue::Number(value as i32)
+    }
+}
+
+impl From<&str> for JsonValue {
+    fn from(value: &str) -> Self {
+        JsonValue::String(value.into())
+    }
+}
+
+fn write_string(f: &mut fmt::Formatter, value: &str) -> fmt::Result {
+    f.write_str("\"")?;
+    // everything that needs escaping is ascii, the runs in between are written as they are.
+    let mut start = 0;
+    for (index, byte) in value.bytes().enumerate() {
+        if byte == b'"' || byte == b'\\' || byte < 0x20 {
+            f.write_str(&value[start..index])?;
+            write!(f, "\\u{:04x}", byte)?;
+            start = index + 1;
+        }
+    }
+    f.write_str(&value[start..])?;
+    f.write_str("\"")
+}
+
+impl fmt::Display for JsonValue {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            JsonValue::Null => f.write_str("null"),
+            JsonValue::Bool(x) => write!(f, "{x}"),
+            JsonValue::Number(x) => write!(f, "{x}"),
+            JsonValue::Hundredths(x) => {
+                let sign = if *x < 0 { "-" } else { "" };
+                let x = x.unsigned_abs();
+                write!(f, "{sign}{}.{:02}", x / 100, x % 100)
+            }
+            JsonValue::String(x) => write_string(f, x),
+            JsonValue::Array(x) => {
+                f.write_str("[")?;
+                for (index, value) in x.iter().enumerate() {
+                    if index != 0 {
+                        f.write_str(",")?;
+                    }
+                    write!(f, "{value}")?;
+                }
+                f.write_str("]")
+            }
+            JsonValue::Object(x) => {
+                f.write_str("{")?;
+                for (index, (key, value)) in x.iter().enumerate() {
+                    if index != 0 {
+                        f.write_str(",")?;
+                    }
+                    write_string(f, key)?;
+                    write!(f, ":{value}")?;
+                }
+                f.write_str("}")
+            }
+        }
+    }
+}
+
+struct Parser<'a> {
+    input: &'a [u8],
+    pos: usize,
+}
+
+impl Parser<'_> {
+    fn skip_whitespace(&mut self) {
+        while matches!(self.input.get(self.pos), Some(b' ' | b'\t' | b'\r' | b'\n')) {
+            self.pos += 1;
+        }
+    }
+
+    fn next(&mut self) -> Option<u8> {
+        let byte = *self.input.get(self.pos)?;
+        self.pos += 1;
+        Some(byte)
+    }
+
+    fn expect(&mut self, literal: &[u8]) -> Option<()> {
+        let end = self.pos + literal.len();
+        (self.input.get(self.pos..end)? == literal).then(|| self.pos = end)
+    }
+
+    fn value(&mut self, depth: usize) -> Option<JsonValue> {
+        if depth > MAX_DEPTH {
+            return None;
+        }
+        self.skip_whitespace();
+        match *self.input.get(self.pos)? {
+            b'n' => self.expect(b"null").map(|_| JsonValue::Null),
+            b't' => self.expect(b"true").map(|_| JsonValue::Bool(true)),
+            b'f' => self.expect(b"false").map(|_| JsonValue::Bool(false)),
+            b'"' => self.string().map(JsonValue::String),
+            b'[' => {
+                self.pos += 1;
+                let mut values = Vec::new();
+                if !self.close(b']') {
+                    loop {
+                        values.push(self.value(depth + 1)?);
+                        if self.close(b']') {
+                            break;
+                        }
+                        self.separator(b',')?;
+                    }
+                }
+                Some(JsonValue::Array(values))
+            }
+            b'{' => {
+                self.pos += 1;
+                let mut members = Vec::new();
+                if !self.close(b'}') {
+                    loop {
+                        self.skip_whitespace();
+                        let key = self.string()?;
+                        self.separator(b':')?;
+                        members.push((key, self.value(depth + 1)?));
+                        if self.close(b'}') {
+                            break;
+                        }
+                        self.separator(b',')?;
+                    }
+                }
+                Some(JsonValue::Object(members))
+            }
+            _ => self.number(),
+        }
+    }
+
+    // consumes `end` if it is the next token.
+    fn close(&mut self, end: u8) -> bool {
+        self.skip_whitespace();
+        let found = self.input.get(self.pos) == Some(&end);
+        self.pos += found as usize;
+        found
+    }
+
+    fn separator(&mut self, separator: u8) -> Option<()> {
+        self.skip_whitespace();
+        (self.next()? == separator).then_some(())
+    }
+
+    fn number(&mut self) -> Option<JsonValue> {
+        let start = self.pos;
+        if self.input.get(self.pos) == Some(&b'-') {
+            self.pos += 1;
+        }
+        let digits = self.pos;
+        while self.input.get(self.pos).is_some_and(|x| x.is_ascii_digit()) {
+            self.pos += 1;
+        }
+        // no leading zeros, and no fractions or exponents.
+        let len = self.pos - digits;
+        if len == 0 || (len > 1 && self.input[digits] == b'0') {
+            return None;
+        }
+        if matches!(self.input.get(self.pos), Some(b'.' | b'e' | b'E')) {
+            return None;
+        }
+        let mut value = 0i32;
+        for digit in &self.input[digits..self.pos] {
+            value = value.checked_mul(10)?.checked_add((digit - b'0') as i32)?;
+        }
+        if self.input[start] == b'-' {
+            value = -value;
+        }
+        Some(JsonValue::Number(value))
+    }
+
+    fn string(&mut self) -> Option<String> {
+        if self.next()? != b'"' {
+            return None;
+        }
+        let mut out = Vec::new();
+        loop {
+            match self.next()? {
+                b'"' => break,
+                b'\\' => {
+                    let escaped = match self.next()? {
+                        b'"' => '"',
+                        b'\\' => '\\',
+                        b'/' => '/',
+                        b'b' => '\u{8}',
+                        b'f' => '\u{c}',
+                        b'n' => '\n',
+                        b'r' => '\r',
+                        b't' => '\t',
+                        b'u' => self.unicode_escape()?,
+                        _ => return None,
+                    };
+                    let mut buf = [0; 4];
+                    out.extend_from_slice(escaped.encode_utf8(&mut buf).as_bytes());
+                }
+                x if x < 0x20 => return None,
+                x => out.push(x),
+            }
+        }
+        // the input was a str, unescaped bytes can't break the encoding.
+        String::from_utf8(out).ok()
+    }
+
+    // the part after "\u", surrogate pairs come as two escapes.
+    fn unicode_escape(&mut self) -> Option<char> {
+        let high = self.hex4()?;
+        if !(0xd800..0xdc00).contains(&high) {
+            return char::from_u32(high);
+        }
+        self.expect(b"\\u")?;
+        let low = self.hex4()?;
+        if !(0xdc00..0xe000).contains(&low) {
+            return None;
+        }
+        char::from_u32(0x10000 + ((high - 0xd800) << 10) + (low - 0xdc00))
+    }
+
+    fn hex4(&mut self) -> Option<u32> {
+        let digits = self.input.get(self.pos..self.pos + 4)?;
+        self.pos += 4;
+        digits
+            .iter()
+            .try_fold(0, |value, x| Some(value << 4 | (*x as char).to_digit(16)?))
+    }
+}
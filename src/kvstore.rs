@@ -0,0 +1,450 @@
+//KV store
+//keeps small crc-checked key/value records as an append-only log in two flash pages.
+//the pages take turns being active: when one fills up, the newest value of every key is copied
+//to the other one and the old page is erased, so each page is erased once per fill.
+
+extern crate alloc;
+use alloc::vec;
+use alloc::vec::Vec;
+
+use defmt::{info, warn};
+use num_enum::IntoPrimitive;
+
+pub const STORE_PAGE_SIZE: usize = 2048;
+pub const STORE_MAX_VALUE: usize = 512;
+
+const PAGE_MAGIC: u32 = 0x5654_4b53; // "SKTV"
+const PAGE_HEADER_LEN: usize = 8;
+const RECORD_HEADER_LEN: usize = 8;
+// flash is programmed a double word at a time, so records are padded to it.
+pub const PROGRAM_UNIT: usize = 8;
+const ERASED: u8 = 0xff;
+
+#[derive(Debug, Clone, Copy, PartialEq, defmt::Format, IntoPrimitive)]
+#[repr(u16)]
+pub enum StoreKey {
+    NetConfig = 1,
+    DhcpLeases = 2,
+    AuthToken = 3,
+}
+
+#[derive(Debug, Clone, Copy, defmt::Format)]
+pub enum StoreError {
+    Flash,
+    TooLarge,
+    Full,
+}
+
+// the two store pages, offsets are relative to the start of a page.
+pub trait FlashBackend {
+    fn read(&self, page: usize, offset: usize, buf: &mut [u8]);
+    // programs erased flash, offset and length are multiples of a double word.
+    fn write(&mut self, page: usize, offset: usize, data: &[u8]) -> Result<(), StoreError>;
+    fn erase(&mut self, page: usize) -> Result<(), StoreError>;
+}
+
+// simulates the store pages in ram with the same program/erase rules, to exercise the store off target.
+#[cfg(test)]
+pub struct RamFlash {
+    pages: [Vec<u8>; 2],
+}
+
+#[cfg(test)]
+impl RamFlash {
+    pub fn new() -> Self {
+        RamFlash {
+            pages: [vec![ERASED; STORE_PAGE_SIZE], vec![ERASED; STORE_PAGE_SIZE]],
+        }
+    }
+}
+
+#[cfg(test)]
+impl FlashBackend for RamFlash {
+    fn read(&self, page: usize, offset: usize, buf: &mut [u8]) {
+        buf.copy_from_slice(&self.pages[page][offset..offset + buf.len()]);
+    }
+
+    fn write(&mut self, page: usize, offset: usize, data: &[u8]) -> Result<(), StoreError> {
+        let target = self.pages[page]
+            .get_mut(offset..offset + data.len())
+            .ok_or(StoreError::Flash)?;
+        // real flash refuses to program a double word twice.
+        if !offset.is_multiple_of(PROGRAM_UNIT)
+            || !data.len().is_multiple_of(PROGRAM_UNIT)
+            || target.iter().any(|x| *x != ERASED)
+        {
+            return Err(StoreError::Flash);
+        }
+        target.copy_from_slice(data);
+        Ok(())
+    }
+
+    fn erase(&mut self, page: usize) -> Result<(), StoreError> {
+        self.pages[page].fill(ERASED);
+        Ok(())
+    }
+}
+
+struct Record {
+    key: u16,
+    offset: usize,
+    len: usize,
+}
+
+pub struct KvStore<F: FlashBackend> {
+    flash: F,
+    active: usize,
+    generation: u32,
+    // where the next record goes in the active page.
+    free: usize,
+}
+
+impl<F: FlashBackend> KvStore<F> {
+    pub fn mount(flash: F) -> Self {
+        let mut store = KvStore {
+            flash,
+            active: 0,
+            generation: 0,
+            free: PAGE_HEADER_LEN,
+        };
+
+        // both pages are valid if we lost power between copying and erasing, the newer one wins.
+        store.active = match [store.page_generation(0), store.page_generation(1)] {
+            [Some(a), Some(b)] if (b.wrapping_sub(a) as i32) > 0 => 1,
+            [Some(_), _] => 0,
+            [None, Some(_)] => 1,
+            [None, None] => {
+                info!("formatting config store");
+                if store.format(0).is_err() {
+                    warn!("failed to format config store");
+                }
+                0
+            }
+        };
+        store.generation = store.page_generation(store.active).unwrap_or(0);
+
+        // data of a record whose header never made it to flash can't be programmed over,
+        // so the next write has to move to the other page.
+        let (_, end) = store.records();
+        store.free = if store.blank(store.active, end) {
+            end
+        } else {
+            STORE_PAGE_SIZE
+        };
+        store
+    }
+
+    pub fn get(&self, key: StoreKey) -> Option<Vec<u8>> {
+        let key = u16::from(key);
+        let (records, _) = self.records();
+        records
+            .iter()
+            .rev()
+            .find(|x| x.key == key)
+            .map(|x| self.record_data(self.active, x))
+    }
+
+    pub fn set(&mut self, key: StoreKey, value: &[u8]) -> Result<(), StoreError> {
+        if value.len() > STORE_MAX_VALUE {
+            return Err(StoreError::TooLarge);
+        }
+        // rewriting a value we already have would only wear the flash.
+        if self.get(key).as_deref() == Some(value) {
+            return Ok(());
+        }
+
+        let key = u16::from(key);
+        let size = record_size(value.len());
+        if self.free + size > STORE_PAGE_SIZE {
+            return self.compact(key, value);
+        }
+        self.append(self.active, self.free, key, value)?;
+        self.free += size;
+        Ok(())
+    }
+
+    // moves the newest value of every key, plus the new one, to the other page.
+    fn compact(&mut self, key: u16, value: &[u8]) -> Result<(), StoreError> {
+        let target = 1 - self.active;
+        self.flash.erase(target)?;
+
+        let (records, _) = self.records();
+        let mut keys = vec![key];
+        let mut offset = PAGE_HEADER_LEN;
+        for record in records.iter().rev() {
+            if keys.contains(&record.key) {
+                continue;
+            }
+            keys.push(record.key);
+            let data = self.record_data(self.active, record);
+            offset = self.append_checked(target, offset, record.key, &data)?;
+        }
+        offset = self.append_checked(target, offset, key, value)?;
+
+        // the header goes last, until it is written the old page stays the valid one.
+        let generation = self.generation.wrapping_add(1);
+        self.write_header(target, generation)?;
+        if self.flash.erase(self.active).is_err() {
+            warn!("failed to erase old config page");
+        }
+
+        info!("config store moved to page {}", target);
+        self.active = target;
+        self.generation = generation;
+        self.free = offset;
+        Ok(())
+    }
+
+    fn append_checked(
+        &mut self,
+        page: usize,
+        offset: usize,
+        key: u16,
+        value: &[u8],
+    ) -> Result<usize, StoreError> {
+        let next = offset + record_size(value.len());
+        if next > STORE_PAGE_SIZE {
+            return Err(StoreError::Full);
+        }
+        self.append(page, offset, key, value)?;
+        Ok(next)
+    }
+
+    // data first and header last, a record without a valid header is never read back.
+    fn append(
+        &mut self,
+        page: usize,
+        offset: usize,
+        key: u16,
+        value: &[u8],
+    ) -> Result<(), StoreError> {
+        if !value.is_empty() {
+            let mut data = value.to_vec();
+            data.resize(padded(value.len()), ERASED);
+            self.flash.write(page, offset + RECORD_HEADER_LEN, &data)?;
+        }
+
+        let len = value.len() as u16;
+        let mut header = [0u8; RECORD_HEADER_LEN];
+        header[0..2].copy_from_slice(&key.to_le_bytes());
+        header[2..4].copy_from_slice(&len.to_le_bytes());
+        header[4..8].copy_from_slice(&record_crc(key, len, value).to_le_bytes());
+        self.flash.write(page, offset, &header)
+    }
+
+    fn format(&mut self, page: usize) -> Result<(), StoreError> {
+        self.flash.erase(page)?;
+        self.write_header(page, 0)
+    }
+
+    fn write_header(&mut self, page: usize, generation: u32) -> Result<(), StoreError> {
+        let mut header = [0u8; PAGE_HEADER_LEN];
+        header[0..4].copy_from_slice(&PAGE_MAGIC.to_le_bytes());
+        header[4..8].copy_from_slice(&generation.to_le_bytes());
+        self.flash.write(page, 0, &header)
+    }
+
+    fn page_generation(&self, page: usize) -> Option<u32> {
+        let mut header = [0u8; PAGE_HEADER_LEN];
+        self.flash.read(page, 0, &mut header);
+        (header[0..4] == PAGE_MAGIC.to_le_bytes())
+            .then(|| u32::from_le_bytes(header[4..8].try_into().unwrap()))
+    }
+
+    // the valid records of the active page in write order, and where its log ends.
+    fn records(&self) -> (Vec<Record>, usize) {
+        let mut records = Vec::<Record>::new();
+        let mut offset = PAGE_HEADER_LEN;
+
+        while offset + RECORD_HEADER_LEN <= STORE_PAGE_SIZE {
+            let mut header = [0u8; RECORD_HEADER_LEN];
+            self.flash.read(self.active, offset, &mut header);
+            if header == [ERASED; RECORD_HEADER_LEN] {
+                break;
+            }
+
+            let record = Record {
+                key: u16::from_le_bytes([header[0], header[1]]),
+                offset,
+                len: u16::from_le_bytes([header[2], header[3]]) as usize,
+            };
+            let next = offset + record_size(record.len);
+            if next > STORE_PAGE_SIZE {
+                break;
+            }
+            let crc = u32::from_le_bytes(header[4..8].try_into().unwrap());
+            let data = self.record_data(self.active, &record);
+            if record_crc(record.key, record.len as u16, &data) == crc {
+                records.push(record);
+            } else {
+                warn!("skipping corrupt config record at {}", offset);
+            }
+            offset = next;
+        }
+        (records, offset)
+    }
+
+    fn record_data(&self, page: usize, record: &Record) -> Vec<u8> {
+        let mut data = vec![0u8; record.len];
+        self.flash
+            .read(page, record.offset + RECORD_HEADER_LEN, &mut data);
+        data
+    }
+
+    fn blank(&self, page: usize, from: usize) -> bool {
+        let mut word = [0u8; PROGRAM_UNIT];
+        (from..STORE_PAGE_SIZE).step_by(PROGRAM_UNIT).all(|offset| {
+            self.flash.read(page, offset, &mut word);
+            word == [ERASED; PROGRAM_UNIT]
+        })
+    }
+}
+
+fn padded(len: usize) -> usize {
+    len.div_ceil(PROGRAM_UNIT) * PROGRAM_UNIT
+}
+
+fn record_size(len: usize) -> usize {
+    RECORD_HEADER_LEN + padded(len)
+}
+
+fn record_crc(key: u16, len: u16, value: &[u8]) -> u32 {
+    crc32(&[&key.to_le_bytes(), &len.to_le_bytes(), value])
+}
+
+// crc-32 (ieee), bit by bit as this only runs when config is read or written.
+fn crc32(parts: &[&[u8]]) -> u32 {
+    let mut crc = !0u32;
+    for byte in parts.iter().flat_map(|x| x.iter()) {
+        crc ^= *byte as u32;
+        for _ in 0..8 {
+            crc = (crc >> 1) ^ (0xedb8_8320 & (crc & 1).wrapping_neg());
+        }
+    }
+    !crc
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // what `KvStore::mount` finds after a reset, with the pages as they were left.
+    fn remount(store: KvStore<RamFlash>) -> KvStore<RamFlash> {
+        KvStore::mount(store.flash)
+    }
+
+    #[test]
+    fn overwrite_keeps_newest_value() {
+        let mut store = KvStore::mount(RamFlash::new());
+        store.set(StoreKey::NetConfig, b"first").unwrap();
+        store.set(StoreKey::DhcpLeases, b"leases").unwrap();
+        store.set(StoreKey::NetConfig, b"second").unwrap();
+        assert_eq!(store.get(StoreKey::NetConfig).unwrap(), b"second");
+
+        let store = remount(store);
+        assert_eq!(store.get(StoreKey::NetConfig).unwrap(), b"second");
+        assert_eq!(store.get(StoreKey::DhcpLeases).unwrap(), b"leases");
+        assert_eq!(store.get(StoreKey::AuthToken), None);
+    }
+
+    #[test]
+    fn torn_record_is_skipped() {
+        let mut store = KvStore::mount(RamFlash::new());
+        store.set(StoreKey::AuthToken, b"old token").unwrap();
+
+        // the header made it to flash but the data only partly did.
+        let offset = store.free;
+        let key = u16::from(StoreKey::AuthToken);
+        let mut header = [0u8; RECORD_HEADER_LEN];
+        header[0..2].copy_from_slice(&key.to_le_bytes());
+        header[2..4].copy_from_slice(&9u16.to_le_bytes());
+        header[4..8].copy_from_slice(&record_crc(key, 9, b"new token").to_le_bytes());
+        let mut data = [ERASED; 16];
+        data[0..5].copy_from_slice(b"new t");
+        store.flash.write(0, offset + RECORD_HEADER_LEN, &data).unwrap();
+        store.flash.write(0, offset, &header).unwrap();
+
+        let mut store = remount(store);
+        assert_eq!(store.get(StoreKey::AuthToken).unwrap(), b"old token");
+        // the log goes on after it.
+        store.set(StoreKey::AuthToken, b"newer").unwrap();
+        let store = remount(store);
+        assert_eq!(store.get(StoreKey::AuthToken).unwrap(), b"newer");
+    }
+
+    #[test]
+    fn data_without_header_moves_to_other_page() {
+        let mut store = KvStore::mount(RamFlash::new());
+        store.set(StoreKey::NetConfig, b"config").unwrap();
+
+        // power was lost after the data of a record was written, before its header.
+        let offset = store.free + RECORD_HEADER_LEN;
+        store.flash.write(0, offset, b"lostdata").unwrap();
+
+        let mut store = remount(store);
+        assert_eq!(store.free, STORE_PAGE_SIZE);
+        store.set(StoreKey::DhcpLeases, b"leases").unwrap();
+        assert_eq!(store.active, 1);
+
+        let store = remount(store);
+        assert_eq!(store.get(StoreKey::NetConfig).unwrap(), b"config");
+        assert_eq!(store.get(StoreKey::DhcpLeases).unwrap(), b"leases");
+    }
+
+    #[test]
+    fn full_page_is_compacted() {
+        let mut store = KvStore::mount(RamFlash::new());
+        store.set(StoreKey::NetConfig, b"config").unwrap();
+        let value = [0x5a; 100];
+        let fill = (STORE_PAGE_SIZE - PAGE_HEADER_LEN) / record_size(value.len());
+        for idx in 0..=fill {
+            let mut value = value;
+            value[0] = idx as u8;
+            store.set(StoreKey::DhcpLeases, &value).unwrap();
+            assert_eq!(store.get(StoreKey::DhcpLeases).unwrap(), value);
+        }
+        assert_eq!(store.active, 1);
+        assert_eq!(store.generation, 1);
+        assert_eq!(store.page_generation(0), None);
+        // only the newest value of each key was moved.
+        assert_eq!(store.records().0.len(), 2);
+
+        let store = remount(store);
+        assert_eq!(store.active, 1);
+        assert_eq!(store.get(StoreKey::NetConfig).unwrap(), b"config");
+        assert_eq!(store.get(StoreKey::DhcpLeases).unwrap()[0], fill as u8);
+    }
+
+    #[test]
+    fn too_large_value_is_refused() {
+        let mut store = KvStore::mount(RamFlash::new());
+        let value = [0u8; STORE_MAX_VALUE + 1];
+        assert!(matches!(
+            store.set(StoreKey::DhcpLeases, &value),
+            Err(StoreError::TooLarge)
+        ));
+    }
+
+    // a compaction that lost power before it erased the old page leaves both pages valid.
+    fn both_pages_valid(old: u32, new: u32) -> KvStore<RamFlash> {
+        let mut store = KvStore::mount(RamFlash::new());
+        store.flash.erase(0).unwrap();
+        store.write_header(0, old).unwrap();
+        store.append(0, PAGE_HEADER_LEN, StoreKey::NetConfig.into(), b"old").unwrap();
+        store.write_header(1, new).unwrap();
+        store.append(1, PAGE_HEADER_LEN, StoreKey::NetConfig.into(), b"new").unwrap();
+        remount(store)
+    }
+
+    #[test]
+    fn remount_picks_newest_page() {
+        let store = both_pages_valid(4, 5);
+        assert_eq!(store.active, 1);
+        assert_eq!(store.get(StoreKey::NetConfig).unwrap(), b"new");
+
+        // the generation wrapped around.
+        let store = both_pages_valid(u32::MAX, 0);
+        assert_eq!(store.active, 1);
+        assert_eq!(store.get(StoreKey::NetConfig).unwrap(), b"new");
+    }
+}
@@ -0,0 +1,82 @@
+//L412 flash
+//the config store's two pages on the l412's own flash, see kvstore.rs for what goes in them.
+
+use stm32_hal2::flash::{Bank, Flash};
+
+use crate::kvstore::{FlashBackend, StoreError, PROGRAM_UNIT, STORE_PAGE_SIZE};
+
+// the last two pages of the 128K flash, memory.x keeps the firmware out of them.
+const STORE_FIRST_PAGE: usize = 62;
+const FLASH_BASE: usize = 0x0800_0000;
+
+// OPERR, PROGERR, WRPERR, PGAERR, SIZERR, PGSERR, MISSERR, FASTERR, RDERR, OPTVERR.
+const FLASH_SR_ERRORS: u32 = 0xc3fa;
+
+pub struct L412Flash {
+    flash: Flash,
+}
+
+impl L412Flash {
+    pub fn new(flash: Flash) -> Self {
+        L412Flash { flash }
+    }
+
+    fn address(page: usize, offset: usize) -> usize {
+        FLASH_BASE + (STORE_FIRST_PAGE + page) * STORE_PAGE_SIZE + offset
+    }
+}
+
+impl FlashBackend for L412Flash {
+    fn read(&self, page: usize, offset: usize, buf: &mut [u8]) {
+        let addr = Self::address(page, offset);
+        for (idx, byte) in buf.iter_mut().enumerate() {
+            *byte = unsafe { core::ptr::read_volatile((addr + idx) as *const u8) };
+        }
+    }
+
+    // the hal can only program from the start of a page, so this follows RM0394 3.3.7 itself.
+    fn write(&mut self, page: usize, offset: usize, data: &[u8]) -> Result<(), StoreError> {
+        self.flash.unlock().map_err(|_| StoreError::Flash)?;
+        let regs = &self.flash.regs;
+        while regs.sr.read().bsy().bit_is_set() {}
+        regs.sr.write(|w| unsafe { w.bits(FLASH_SR_ERRORS) });
+        regs.cr.modify(|_, w| w.pg().set_bit());
+
+        let mut addr = Self::address(page, offset) as *mut u32;
+        for chunk in data.chunks_exact(PROGRAM_UNIT) {
+            unsafe {
+                core::ptr::write_volatile(
+                    addr,
+                    u32::from_le_bytes(chunk[0..4].try_into().unwrap()),
+                );
+                core::ptr::write_volatile(
+                    addr.add(1),
+                    u32::from_le_bytes(chunk[4..8].try_into().unwrap()),
+                );
+                addr = addr.add(2);
+            }
+            while regs.sr.read().bsy().bit_is_set() {}
+        }
+
+        regs.cr.modify(|_, w| w.pg().clear_bit());
+        let failed = regs.sr.read().bits() & FLASH_SR_ERRORS != 0;
+        self.flash.lock();
+        if failed {
+            return Err(StoreError::Flash);
+        }
+        Ok(())
+    }
+
+    fn erase(&mut self, page: usize) -> Result<(), StoreError> {
+        self.flash
+            .erase_page(Bank::B1, STORE_FIRST_PAGE + page)
+            .map_err(|_| StoreError::Flash)?;
+        // the data cache may still hold what the page contained before (RM0394 3.3.3).
+        let regs = &self.flash.regs;
+        regs.acr.modify(|_, w| w.dcen().clear_bit());
+        regs.acr.modify(|_, w| w.dcrst().set_bit());
+        regs.acr
+            .modify(|_, w| w.dcrst().clear_bit().dcen().set_bit());
+        Ok(())
+    }
+}
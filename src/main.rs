@@ -1,5 +1,7 @@
 #![no_std]
 #![no_main]
+extern crate alloc;
+use alloc::string::String;
 use core::cell::RefCell;
 
 //runtime
@@ -17,6 +19,7 @@ use stm32_hal2::pac::TIM1;
 // hal
 use stm32_hal2::{
     clocks::{self, Clk48Src, Clocks, CrsSyncSrc},
+    flash::Flash,
     gpio::{Pin, PinMode, Port},
     pac,
     rng::{self, Rng},
@@ -29,19 +32,35 @@ mod cdc_ncm;
 mod ncm_api;
 use ncm_api::NcmApiManager;
 
+mod assets;
 mod dhcp;
+mod dns;
+mod events;
+mod mdns;
 mod http;
+mod inflate;
+mod json;
+mod kvstore;
+use kvstore::KvStore;
+mod l412flash;
+use l412flash::L412Flash;
 mod server;
-use server::TcpServer;
+mod websocket;
+use server::{NetConfig, TcpServer};
 
 mod ncm_netif;
 
 mod usbipserver;
 use usbipserver::UsbIpManager;
 
-static TICKS: Mutex<RefCell<u32>> = Mutex::new(RefCell::new(0u32));
+static TICKS: Mutex<RefCell<u64>> = Mutex::new(RefCell::new(0u64));
 static STATS: Mutex<RefCell<(u32,u32)>> = Mutex::new(RefCell::new((0u32,0u32)));
 static RGB: Mutex<RefCell<(u8, u8, u8)>> = Mutex::new(RefCell::new((0, 0, 0)));
+static LINK_UP: Mutex<RefCell<bool>> = Mutex::new(RefCell::new(false));
+// a new auth token from the api, waiting to be saved.
+static AUTH_TOKEN: Mutex<RefCell<Option<String>>> = Mutex::new(RefCell::new(None));
+// new network settings from the api, waiting to be saved.
+static NET_CONFIG: Mutex<RefCell<Option<NetConfig>>> = Mutex::new(RefCell::new(None));
 
 defmt::timestamp!("{=u32}", { get_counter() });
 fn increase_counter() {
@@ -49,7 +68,12 @@ fn increase_counter() {
         *TICKS.borrow(cs).borrow_mut() += 1;
     })
 }
-fn get_counter() -> u32 {
+// ms since boot, wraps after ~49 days.
+pub fn get_counter() -> u32 {
+    get_uptime() as u32
+}
+// ms since boot, for times that must not wrap.
+pub fn get_uptime() -> u64 {
     with(|cs| *TICKS.borrow(cs).borrow())
 }
 pub fn set_rgb(val: (u8, u8, u8)) {
@@ -76,6 +100,38 @@ pub fn get_stats() -> (u32,u32) {
     with(|cs| *STATS.borrow(cs).borrow())
 }
 
+fn set_link_up(val: bool) {
+    with(|cs| {
+        *LINK_UP.borrow(cs).borrow_mut() = val;
+    })
+}
+pub fn get_link_up() -> bool {
+    with(|cs| *LINK_UP.borrow(cs).borrow())
+}
+
+// bytes of heap in use and free.
+pub fn get_heap_usage() -> (usize, usize) {
+    (HEAP.used(), HEAP.free())
+}
+
+pub fn set_auth_token(val: String) {
+    with(|cs| {
+        *AUTH_TOKEN.borrow(cs).borrow_mut() = Some(val);
+    })
+}
+pub fn take_auth_token() -> Option<String> {
+    with(|cs| AUTH_TOKEN.borrow(cs).borrow_mut().take())
+}
+
+pub fn set_net_config(val: NetConfig) {
+    with(|cs| {
+        *NET_CONFIG.borrow(cs).borrow_mut() = Some(val);
+    })
+}
+pub fn take_net_config() -> Option<NetConfig> {
+    with(|cs| NET_CONFIG.borrow(cs).borrow_mut().take())
+}
+
 #[exception]
 fn SysTick() {
     increase_counter();
@@ -163,6 +219,7 @@ struct ProjectPeriphs {
     rgb: RgbControl,
     adc: AdcControl,
     usb: Peripheral,
+    flash: Flash,
     clk_cfg: Clocks,
 }
 impl ProjectPeriphs {
@@ -213,6 +270,7 @@ impl ProjectPeriphs {
         let adc = AdcControl::new(adc);
         let rgb = RgbControl::new(pwm_timer);
         let usb = Peripheral { regs: dp.USB };
+        let flash = Flash::new(dp.FLASH);
         let _rng = Rng::new(dp.RNG);
         
 
@@ -224,6 +282,7 @@ impl ProjectPeriphs {
         ProjectPeriphs {
             arm,
             usb,
+            flash,
             adc,
             rgb,
             clk_cfg,
@@ -234,6 +293,17 @@ impl ProjectPeriphs {
 #[global_allocator]
 static HEAP: Heap = Heap::empty();
 
+// all of RAM2, RAM holds the stack and the statics, the frame pool among them. the worst case:
+// for good, from init_server:
+//   tcp socket buffers, 4 x (256 + 768)          4096
+//   udp socket buffers (dhcp, dns and mdns)      2568
+//   socket set, 7 x 336                          2352
+//   http connections and routes                  1136
+//   dhcp leases, 16 per pool address             2048 with the default pool
+//   names, the auth token, the latest event      ~400
+//   failed logins, 4 peers x 32                   128
+// that leaves ~3.7K. an open websocket or event stream keeps up to ~1K, and the web server only
+// takes on more work while HTTP_HEAP_RESERVE (3K) of it is free.
 fn init_heap() {
     use core::mem::MaybeUninit;
     const HEAP_SIZE: usize = 0x4000;
@@ -251,7 +321,8 @@ fn main() -> ! {
     let mut ncmapi = NcmApiManager::new();
 
     info!("starting server...");
-    let mut tcpserv = TcpServer::init_server(rng::read() as u32);
+    let store = KvStore::mount(L412Flash::new(periphs.flash));
+    let mut tcpserv = TcpServer::init_server(rng::read() as u32, store);
     periphs.rgb.active_all_pwms();
 
     let mut perfcounter = 0;
@@ -262,6 +333,9 @@ fn main() -> ! {
         let looptime = get_counter();
 
         usbipmanager.run_loop();
+        set_link_up(usbipmanager.link_up());
+        ncmapi.set_packet_filter(usbipmanager.packet_filter());
+        ncmapi.set_ntb_format(usbipmanager.ntb_format());
         ncmapi.process_messages(tcpserv.get_bufs(), usbipmanager.get_bufs());
 
         tcpserv.eth_task(looptime);
@@ -289,6 +363,7 @@ fn finalize_perfcounter(
         debug!("seconds:{} loops: {}", looptime / 1000, cnt);
         set_lps(*cnt);
         set_temp(adc.get_temperature_int());
+        events::publish("telemetry", server::telemetry());
         *cnt = 0;
 
         looptime
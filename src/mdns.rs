@@ -0,0 +1,164 @@
+//mDNS
+//answers <hostname>.local and advertises the http server through DNS-SD.
+
+extern crate alloc;
+use alloc::format;
+use alloc::string::String;
+use alloc::vec::Vec;
+
+use defmt::debug;
+use smoltcp::wire::Ipv4Address;
+
+use crate::dns::{
+    write_name, write_record, DnsHeader, DnsQuestion, DnsRecordTypes, DNS_HEADER_LEN, OPCODE_MASK,
+};
+
+pub const MDNS_PORT: u16 = 5353;
+pub const MDNS_GROUP: Ipv4Address = Ipv4Address::new(224, 0, 0, 251);
+const MDNS_TTL: u32 = 120;
+//RFC 6762 6.7: legacy unicast answers must not be cached for long.
+const MDNS_LEGACY_TTL: u32 = 10;
+const MDNS_MAX_QUESTIONS: u16 = 8;
+
+const CLASS_IN: u16 = 0x0001;
+const CLASS_CACHE_FLUSH: u16 = 0x8000;
+const QCLASS_UNICAST: u16 = 0x8000;
+const FLAGS_RESPONSE: u16 = 0x8400;
+
+const SERVICE_TYPE: &str = "_http._tcp.local";
+const SERVICES_META: &str = "_services._dns-sd._udp.local";
+
+// records we may put in a response.
+const REC_A: u8 = 0x01;
+const REC_SERVICE_PTR: u8 = 0x02;
+const REC_SRV: u8 = 0x04;
+const REC_TXT: u8 = 0x08;
+const REC_META_PTR: u8 = 0x10;
+
+pub struct MdnsReply {
+    pub msg: Vec<u8>,
+    //reply straight to the querier instead of the multicast group.
+    pub unicast: bool,
+}
+
+pub struct MdnsResponder {
+    pub hostname: String,
+    pub serverip: Ipv4Address,
+    pub http_port: u16,
+}
+
+impl MdnsResponder {
+    fn host(&self) -> String {
+        format!("{}.local", self.hostname)
+    }
+
+    fn instance(&self) -> String {
+        format!("{}.{}", self.hostname, SERVICE_TYPE)
+    }
+
+    //`legacy` is set for queries that did not come from port 5353 (RFC 6762 6.7).
+    pub fn recv(&mut self, buf: &[u8], legacy: bool) -> Option<MdnsReply> {
+        let header = DnsHeader::parse(buf)?;
+        if header.is_response() || header.flags & OPCODE_MASK != 0 {
+            return None;
+        }
+
+        let host = self.host();
+        let instance = self.instance();
+        let mut answers = 0u8;
+        let mut additionals = 0u8;
+        let mut unicast = legacy;
+        let mut questions = Vec::<DnsQuestion>::new();
+
+        let mut offset = DNS_HEADER_LEN;
+        for _ in 0..header.qdcount.min(MDNS_MAX_QUESTIONS) {
+            let (question, next) = DnsQuestion::parse(buf, offset)?;
+            offset = next;
+
+            let any = question.record_type() == Some(DnsRecordTypes::Any);
+            let wants = |rtype| any || question.record_type() == Some(rtype);
+            let name = question.name.as_str();
+            let before = answers;
+
+            if name.eq_ignore_ascii_case(&host) && wants(DnsRecordTypes::A) {
+                answers |= REC_A;
+            } else if name.eq_ignore_ascii_case(SERVICE_TYPE) && wants(DnsRecordTypes::Ptr) {
+                answers |= REC_SERVICE_PTR;
+                additionals |= REC_SRV | REC_TXT | REC_A;
+            } else if name.eq_ignore_ascii_case(&instance) {
+                if wants(DnsRecordTypes::Srv) {
+                    answers |= REC_SRV;
+                    additionals |= REC_A;
+                }
+                if wants(DnsRecordTypes::Txt) {
+                    answers |= REC_TXT;
+                }
+            } else if name.eq_ignore_ascii_case(SERVICES_META) && wants(DnsRecordTypes::Ptr) {
+                answers |= REC_META_PTR;
+            }
+
+            if answers != before {
+                unicast |= question.qclass & QCLASS_UNICAST != 0;
+                questions.push(question);
+            }
+        }
+
+        if answers == 0 {
+            return None;
+        }
+        debug!("mdns answering {:02x}", answers);
+        additionals &= !answers;
+
+        let reply = DnsHeader {
+            id: if legacy { header.id } else { 0 },
+            flags: FLAGS_RESPONSE,
+            qdcount: if legacy { questions.len() as u16 } else { 0 },
+            ancount: answers.count_ones() as u16,
+            nscount: 0,
+            arcount: additionals.count_ones() as u16,
+        };
+
+        let mut msg = Vec::<u8>::new();
+        reply.emit(&mut msg);
+        if legacy {
+            questions.iter().for_each(|q| q.emit(&mut msg));
+        }
+        self.write_records(&mut msg, answers, legacy, &host, &instance);
+        self.write_records(&mut msg, additionals, legacy, &host, &instance);
+
+        Some(MdnsReply { msg, unicast })
+    }
+
+    fn write_records(&self, out: &mut Vec<u8>, records: u8, legacy: bool, host: &str, instance: &str) {
+        let (ttl, unique) = if legacy {
+            (MDNS_LEGACY_TTL, CLASS_IN)
+        } else {
+            (MDNS_TTL, CLASS_IN | CLASS_CACHE_FLUSH)
+        };
+
+        if records & REC_A != 0 {
+            write_record(out, host, DnsRecordTypes::A, unique, ttl, self.serverip.as_bytes());
+        }
+        if records & REC_SERVICE_PTR != 0 {
+            let mut rdata = Vec::<u8>::new();
+            write_name(&mut rdata, instance);
+            write_record(out, SERVICE_TYPE, DnsRecordTypes::Ptr, CLASS_IN, ttl, &rdata);
+        }
+        if records & REC_SRV != 0 {
+            //priority, weight, port, target
+            let mut rdata = Vec::<u8>::new();
+            rdata.extend_from_slice(&[0, 0, 0, 0]);
+            rdata.extend_from_slice(&self.http_port.to_be_bytes());
+            write_name(&mut rdata, host);
+            write_record(out, instance, DnsRecordTypes::Srv, unique, ttl, &rdata);
+        }
+        if records & REC_TXT != 0 {
+            write_record(out, instance, DnsRecordTypes::Txt, unique, ttl, b"\x06path=/");
+        }
+        if records & REC_META_PTR != 0 {
+            let mut rdata = Vec::<u8>::new();
+            write_name(&mut rdata, SERVICE_TYPE);
+            write_record(out, SERVICES_META, DnsRecordTypes::Ptr, CLASS_IN, ttl, &rdata);
+        }
+    }
+}
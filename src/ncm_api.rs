@@ -3,46 +3,70 @@
 
 extern crate alloc;
 use crate::cdc_ncm::EP_DATA_BUF_SIZE;
-use crate::cdc_ncm::{NCM_MAX_IN_SIZE, NCM_MAX_OUT_SIZE};
+use crate::cdc_ncm::{EthPacketFilter, NtbFormat};
+use crate::cdc_ncm::{NCM_MAX_IN_SIZE, NCM_MAX_OUT_SIZE, NCM_OUT_MAX_DATAGRAMS, NDP_IN_DIVISOR};
+use crate::get_counter;
 use alloc::vec::Vec;
 use core::array::TryFromSliceError;
 use core::cmp::Ordering;
 pub const NTH16_SIGNATURE: &[u8] = "NCMH".as_bytes();
 pub const NDP16_SIGNATURE: &[u8] = "NCM0".as_bytes();
+pub const NTH32_SIGNATURE: &[u8] = "ncmh".as_bytes();
+pub const NDP32_SIGNATURE: &[u8] = "ncm0".as_bytes();
+// frames wait up to this long for others to share their IN NTB with.
+const TX_FLUSH_MS: u32 = 2;
 
-use crate::ncm_netif::{EthRingBuffers, MTU};
+use crate::ncm_netif::{EthRingBuffers, PacketBuf, MAX_FRAME_SIZE};
 use crate::usbipserver::UsbRingBuffers;
 use concurrent_queue::PushError;
 
 use defmt::{debug, info, warn};
 
+//lengths and indices are u16 in an NTH16/NDP16, u32 in an NTH32/NDP32.
 #[repr(C)]
 #[derive(Debug, defmt::Format, Clone)]
 pub struct NCMTransferHeader {
     pub signature: u32,
     pub headerlen: u16,
     pub sequence: u16,
-    pub blocklen: u16,
-    pub ndpindex: u16,
+    pub blocklen: u32,
+    pub ndpindex: u32,
 }
 
-impl Default for NCMTransferHeader {
-    fn default() -> Self {
+impl NCMTransferHeader {
+    pub fn new(format: NtbFormat) -> Self {
+        let signature = match format {
+            NtbFormat::Ntb16 => NTH16_SIGNATURE,
+            NtbFormat::Ntb32 => NTH32_SIGNATURE,
+        };
         NCMTransferHeader {
-            signature: u32::from_le_bytes(NTH16_SIGNATURE.try_into().unwrap()),
-            headerlen: 0x000c,
+            signature: u32::from_le_bytes(signature.try_into().unwrap()),
+            headerlen: format.nth_len() as u16,
             sequence: 0,
             blocklen: 0,
-            ndpindex: 0x0c,
+            ndpindex: format.nth_len() as u32,
+        }
+    }
+
+    pub fn format(&self) -> NtbFormat {
+        match self.signature.to_le_bytes() == NTH32_SIGNATURE {
+            true => NtbFormat::Ntb32,
+            false => NtbFormat::Ntb16,
         }
     }
 }
 
+impl Default for NCMTransferHeader {
+    fn default() -> Self {
+        NCMTransferHeader::new(NtbFormat::Ntb16)
+    }
+}
+
 #[repr(C)]
 #[derive(Clone, Default)]
-pub struct NCMDatagram16 {
-    pub index: u16,
-    pub length: u16,
+pub struct NCMDatagram {
+    pub index: u32,
+    pub length: u32,
 }
 
 #[repr(C)]
@@ -50,24 +74,40 @@ pub struct NCMDatagram16 {
 pub struct NCMDatagramPointerTable {
     pub signature: u32,
     pub length: u16,
-    pub nextndpindex: u16,
-    pub datagrams: Vec<NCMDatagram16>,
+    pub nextndpindex: u32,
+    pub datagrams: Vec<NCMDatagram>,
 }
 
-impl Default for NCMDatagramPointerTable {
-    fn default() -> Self {
+impl NCMDatagramPointerTable {
+    pub fn new(format: NtbFormat) -> Self {
+        let signature = match format {
+            NtbFormat::Ntb16 => NDP16_SIGNATURE,
+            NtbFormat::Ntb32 => NDP32_SIGNATURE,
+        };
         NCMDatagramPointerTable {
-            signature: u32::from_le_bytes(NDP16_SIGNATURE.try_into().unwrap()),
+            signature: u32::from_le_bytes(signature.try_into().unwrap()),
             length: 0x10,
             nextndpindex: 0,
-            datagrams: Vec::<NCMDatagram16>::new(),
+            datagrams: Vec::<NCMDatagram>::new(),
+        }
+    }
+
+    pub fn format(&self) -> NtbFormat {
+        match self.signature.to_le_bytes() == NDP32_SIGNATURE {
+            true => NtbFormat::Ntb32,
+            false => NtbFormat::Ntb16,
         }
     }
 }
 
+impl Default for NCMDatagramPointerTable {
+    fn default() -> Self {
+        NCMDatagramPointerTable::new(NtbFormat::Ntb16)
+    }
+}
+
 /// A USB stack error.
-#[derive(Debug)]
-#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[derive(Debug, defmt::Format)]
 pub enum NCMError {
     //
     TryFromSliceError,
@@ -93,16 +133,34 @@ pub trait ToBytes {
     fn conv_to_bytes(&self) -> Vec<u8>;
 }
 
+//a little endian field of `width` bytes.
+fn read_le(buf: &[u8], offset: usize, width: usize) -> Result<u32, NCMError> {
+    let bytes = buf.get(offset..offset + width).ok_or(NCMError::SizeError)?;
+    Ok(bytes.iter().rev().fold(0, |acc, x| acc << 8 | *x as u32))
+}
+
+fn write_le(bytes: &mut Vec<u8>, value: u32, width: usize) {
+    bytes.extend_from_slice(&value.to_le_bytes()[..width]);
+}
+
 impl ToBytes for NCMDatagramPointerTable {
     fn conv_to_bytes(&self) -> Vec<u8> {
+        let width = self.format().width();
         let mut bytes = Vec::<u8>::new();
         bytes.extend_from_slice(&self.signature.to_le_bytes());
         bytes.extend_from_slice(&self.length.to_le_bytes());
-        bytes.extend_from_slice(&self.nextndpindex.to_le_bytes());
+        //an NDP32 has reserved fields around the next index.
+        if width == 4 {
+            write_le(&mut bytes, 0, 2);
+        }
+        write_le(&mut bytes, self.nextndpindex, width);
+        if width == 4 {
+            write_le(&mut bytes, 0, 4);
+        }
 
         self.datagrams.iter().for_each(|x| {
-            bytes.extend_from_slice(x.index.to_le_bytes().as_slice());
-            bytes.extend_from_slice(x.length.to_le_bytes().as_slice());
+            write_le(&mut bytes, x.index, width);
+            write_le(&mut bytes, x.length, width);
         });
 
         bytes
@@ -111,12 +169,13 @@ impl ToBytes for NCMDatagramPointerTable {
 
 impl ToBytes for NCMTransferHeader {
     fn conv_to_bytes(&self) -> Vec<u8> {
+        let width = self.format().width();
         let mut bytes = Vec::<u8>::new();
         bytes.extend_from_slice(&self.signature.to_le_bytes());
         bytes.extend_from_slice(&self.headerlen.to_le_bytes());
         bytes.extend_from_slice(&self.sequence.to_le_bytes());
-        bytes.extend_from_slice(&self.blocklen.to_le_bytes());
-        bytes.extend_from_slice(&self.ndpindex.to_le_bytes());
+        write_le(&mut bytes, self.blocklen, width);
+        write_le(&mut bytes, self.ndpindex, width);
 
         bytes
     }
@@ -125,17 +184,19 @@ impl ToBytes for NCMTransferHeader {
 impl TryInto<NCMTransferHeader> for &[u8] {
     type Error = NCMError;
     fn try_into(self) -> Result<NCMTransferHeader, Self::Error> {
-        let signature = u32::from_le_bytes(self[0..4].try_into()?);
-        if signature != u32::from_le_bytes(NTH16_SIGNATURE.try_into()?) {
-            return Err(NCMError::InvalidSignature);
-        }
+        let signature = read_le(self, 0, 4)?;
+        let width = match signature.to_le_bytes().as_slice() {
+            NTH16_SIGNATURE => 2,
+            NTH32_SIGNATURE => 4,
+            _ => return Err(NCMError::InvalidSignature),
+        };
 
         Ok(NCMTransferHeader {
-            signature: u32::from_le_bytes(self[0..4].try_into()?),
-            headerlen: u16::from_le_bytes(self[4..6].try_into()?),
-            sequence: u16::from_le_bytes(self[6..8].try_into()?),
-            blocklen: u16::from_le_bytes(self[8..10].try_into()?),
-            ndpindex: u16::from_le_bytes(self[10..12].try_into()?),
+            signature,
+            headerlen: read_le(self, 4, 2)? as u16,
+            sequence: read_le(self, 6, 2)? as u16,
+            blocklen: read_le(self, 8, width)?,
+            ndpindex: read_le(self, 8 + width, width)?,
         })
     }
 }
@@ -143,24 +204,29 @@ impl TryInto<NCMTransferHeader> for &[u8] {
 impl TryInto<NCMDatagramPointerTable> for &[u8] {
     type Error = NCMError;
     fn try_into(self) -> Result<NCMDatagramPointerTable, Self::Error> {
-        let signature = u32::from_le_bytes(self[0..4].try_into()?);
-
-        if signature != u32::from_le_bytes(NDP16_SIGNATURE.try_into()?) {
-            return Err(NCMError::InvalidSignature);
-        }
+        let signature = read_le(self, 0, 4)?;
+        let width = match signature.to_le_bytes().as_slice() {
+            NDP16_SIGNATURE => 2,
+            NDP32_SIGNATURE => 4,
+            _ => return Err(NCMError::InvalidSignature),
+        };
 
-        let length = u16::from_le_bytes(self[4..6].try_into()?);
-        let nextndpindex = u16::from_le_bytes(self[6..8].try_into()?);
+        let length = read_le(self, 4, 2)? as u16;
+        let nextndpindex = read_le(self, 4 + width, width)?;
 
-        let datagrams = self[8..(length as usize)]
-            .to_vec()
-            .chunks(4)
-            .map(|win| NCMDatagram16 {
-                index: u16::from_le_bytes(win[0..2].try_into().unwrap()),
-                length: u16::from_le_bytes(win[2..4].try_into().unwrap()),
+        let datagrams = self
+            .get(4 * width..(length as usize))
+            .ok_or(NCMError::SizeError)?
+            .chunks_exact(2 * width)
+            .map(|win| NCMDatagram {
+                index: read_le(win, 0, width).unwrap_or_default(),
+                length: read_le(win, width, width).unwrap_or_default(),
             })
-            .filter(|x| x.length != 0)
-            .collect::<Vec<NCMDatagram16>>();
+            //the list ends at the first null entry, and one past the most the host may send is
+            //enough to tell it sent too many.
+            .take_while(|x| x.index != 0 && x.length != 0)
+            .take(NCM_OUT_MAX_DATAGRAMS + 1)
+            .collect::<Vec<NCMDatagram>>();
 
         Ok(NCMDatagramPointerTable {
             signature,
@@ -178,7 +244,6 @@ enum IpRxState {
 
 enum IpTxState {
     Ready,
-    Header,
     Sending,
     Zlp,
 }
@@ -188,15 +253,27 @@ pub struct NcmApiManager {
     txstate: IpTxState,
     currheader: NCMTransferHeader,
     currndp: NCMDatagramPointerTable,
+    //the next datagram of currndp to hand to the netif.
+    currdgram: usize,
+    //ntbs and frames thrown away, the sequence number due next and how often it wasn't.
+    rxdropped: u32,
+    rxsequence: Option<u16>,
+    rxseqgaps: u32,
     currcnt: usize,
     txtransactioncnt: usize,
     txheader: NCMTransferHeader,
     txdatagram: NCMDatagramPointerTable,
-    ncmmsgtxbuf: [u8; NCM_MAX_OUT_SIZE],
-    ncmmsgrxbuf: [u8; NCM_MAX_IN_SIZE],
+    ncmmsgtxbuf: [u8; NCM_MAX_IN_SIZE],
+    ncmmsgrxbuf: [u8; NCM_MAX_OUT_SIZE],
     usbmsgtotlen: usize,
-    currtxdatalen: usize,
+    //end of the datagrams in the ntb being filled, and when its first one was queued.
+    txpos: usize,
+    txstarted: u32,
+    //a frame taken off the queue that hasn't gone into an ntb yet.
+    txnext: Option<PacketBuf>,
     rxbufready: bool,
+    packet_filter: EthPacketFilter,
+    ntb_format: NtbFormat,
 }
 
 impl NcmApiManager {
@@ -206,22 +283,137 @@ impl NcmApiManager {
             txstate: IpTxState::Ready,
             currheader: NCMTransferHeader::default(),
             currndp: NCMDatagramPointerTable::default(),
+            currdgram: 0,
+            rxdropped: 0,
+            rxsequence: None,
+            rxseqgaps: 0,
             currcnt: 0,
             txtransactioncnt: 0,
             txheader: NCMTransferHeader::default(),
             txdatagram: NCMDatagramPointerTable::default(),
-            ncmmsgtxbuf: [0u8; NCM_MAX_OUT_SIZE],
-            ncmmsgrxbuf: [0u8; NCM_MAX_IN_SIZE],
+            ncmmsgtxbuf: [0u8; NCM_MAX_IN_SIZE],
+            ncmmsgrxbuf: [0u8; NCM_MAX_OUT_SIZE],
             usbmsgtotlen: 0,
-            currtxdatalen: 0,
+            txpos: NtbFormat::default().nth_len(),
+            txstarted: 0,
+            txnext: None,
             rxbufready: false,
+            packet_filter: EthPacketFilter::default(),
+            ntb_format: NtbFormat::default(),
+        }
+    }
+
+    //checks an ntb header before its block is copied in.
+    fn check_header(&self, header: &NCMTransferHeader) -> Result<(), NCMError> {
+        let format = header.format();
+        let (blocklen, ndpindex) = (header.blocklen as usize, header.ndpindex as usize);
+        //blocklen is bounded first, ndpindex is a 32 bit field in NTH32 and may not be added to.
+        if header.headerlen as usize != format.nth_len()
+            || !(format.nth_len()..=NCM_MAX_OUT_SIZE).contains(&blocklen)
+            || ndpindex < format.nth_len()
+            || ndpindex > blocklen - format.ndp_len()
+        {
+            return Err(NCMError::SizeError);
+        }
+        Ok(())
+    }
+
+    //walks the ndp chain, currndp ends up with the datagrams of all of them. every index is
+    //checked against the block, anything out of place fails the whole ntb.
+    pub fn process_ndp(&mut self) -> Result<(), NCMError> {
+        let block = &self.ncmmsgrxbuf[..self.currheader.blocklen as usize];
+        let format = self.currheader.format();
+        let mut datagrams = Vec::new();
+        let mut ndpindex = self.currheader.ndpindex as usize;
+        let mut visited = [0usize; NCM_OUT_MAX_DATAGRAMS];
+        //every ndp points at a datagram at least, a longer chain has to be a loop.
+        for hop in 0..NCM_OUT_MAX_DATAGRAMS {
+            if ndpindex == 0 {
+                break;
+            }
+            if !ndpindex.is_multiple_of(4) || visited[..hop].contains(&ndpindex) {
+                return Err(NCMError::ArrayError);
+            }
+            visited[hop] = ndpindex;
+            let ndp: NCMDatagramPointerTable =
+                block.get(ndpindex..).ok_or(NCMError::SizeError)?.try_into()?;
+            if ndp.format() != format {
+                return Err(NCMError::InvalidSignature);
+            }
+            for dgram in ndp.datagrams {
+                let start = dgram.index as usize;
+                let end = start.checked_add(dgram.length as usize);
+                if start < format.nth_len() || end.is_none_or(|x| x > block.len()) {
+                    return Err(NCMError::SizeError);
+                }
+                //more than the host was told it may put in one ntb.
+                if datagrams.len() == NCM_OUT_MAX_DATAGRAMS {
+                    return Err(NCMError::ArrayError);
+                }
+                datagrams.push(dgram);
+            }
+            ndpindex = ndp.nextndpindex as usize;
         }
+        if ndpindex != 0 {
+            return Err(NCMError::ArrayError);
+        }
+        self.currndp.datagrams = datagrams;
+        self.currdgram = 0;
+        Ok(())
+    }
+
+    fn drop_ntb(&mut self, error: NCMError) {
+        self.rxdropped += 1;
+        warn!("dropped an ntb: {}, {} so far", error, self.rxdropped);
     }
 
-    pub fn process_ndp(&mut self) {
-        self.currndp = self.ncmmsgrxbuf[(self.currheader.ndpindex as usize)..]
-            .try_into()
-            .unwrap();
+    pub fn set_packet_filter(&mut self, filter: EthPacketFilter) {
+        self.packet_filter = filter;
+    }
+
+    //the IN path switches formats between ntbs only, this is called again every loop.
+    pub fn set_ntb_format(&mut self, format: NtbFormat) {
+        let idle = matches!(self.txstate, IpTxState::Ready) && self.txdatagram.datagrams.is_empty();
+        if format != self.ntb_format && idle {
+            info!("switching to {}", format);
+            self.ntb_format = format;
+            self.txheader = NCMTransferHeader::new(format);
+            self.txdatagram = NCMDatagramPointerTable::new(format);
+            self.txpos = format.nth_len();
+        }
+    }
+
+    //whether a frame of `len` bytes still fits in the ntb being filled, along with its ndp entry.
+    fn tx_fits(&self, len: usize) -> bool {
+        let format = self.ntb_format;
+        let ndplen = format.ndp_len() + format.entry_len() * (self.txdatagram.datagrams.len() + 2);
+        align_in(self.txpos + len) + ndplen <= NCM_MAX_IN_SIZE
+    }
+
+    //closes the ntb being filled, the ndp goes after the datagrams.
+    fn finish_ntb(&mut self) {
+        let format = self.ntb_format;
+        self.txdatagram.datagrams.push(NCMDatagram::default());
+        self.txdatagram.length =
+            (format.ndp_len() + format.entry_len() * self.txdatagram.datagrams.len()) as u16;
+        let datagramvec = self.txdatagram.conv_to_bytes();
+        self.usbmsgtotlen = self.txpos + datagramvec.len();
+        self.ncmmsgtxbuf[self.txpos..self.usbmsgtotlen].copy_from_slice(datagramvec.as_slice());
+
+        self.txheader.ndpindex = self.txpos as u32;
+        self.txheader.blocklen = self.usbmsgtotlen as u32;
+        let headervec = self.txheader.conv_to_bytes();
+        self.txheader.sequence = self.txheader.sequence.wrapping_add(1);
+        self.ncmmsgtxbuf[0..headervec.len()].copy_from_slice(headervec.as_slice());
+        debug!(
+            "sending {} datagrams in {} bytes",
+            self.txdatagram.datagrams.len() - 1,
+            self.usbmsgtotlen
+        );
+
+        self.txdatagram.datagrams.clear();
+        self.txpos = format.nth_len();
+        self.txstate = IpTxState::Sending;
     }
 
     fn restart_rx(&mut self) {
@@ -232,61 +424,38 @@ impl NcmApiManager {
     pub fn process_messages(&mut self, eth_buffers: EthRingBuffers, usb_buffers: UsbRingBuffers) {
         let (rxq, txq) = eth_buffers;
         let (usbrxring, usbtxring) = usb_buffers;
-        const TOTAL_HEADER_SIZE: usize = 0x001c;
         //TX HANDLING
         match self.txstate {
             IpTxState::Ready => {
-                //we only need to copy the buffer
-                if let Ok((msg_len, msg)) = txq.pop() {
-                    debug!("sending {:02x}", msg[0..msg_len]);
-                    //create a new datagram table entry for this message
-                    self.currtxdatalen = msg_len;
-                    self.txdatagram.datagrams.push(NCMDatagram16 {
-                        index: TOTAL_HEADER_SIZE as u16,
-                        length: msg_len as u16,
-                    });
-                    self.txdatagram.datagrams.push(NCMDatagram16 {
-                        index: 0,
-                        length: 0,
-                    });
-
-                    self.usbmsgtotlen = TOTAL_HEADER_SIZE + msg_len;
-                    self.txheader.blocklen = self.usbmsgtotlen as u16;
-
-                    let headervec = self.txheader.conv_to_bytes();
-                    self.txheader.sequence = self.txheader.sequence.wrapping_add(1);
-                    let datagramvec = self.txdatagram.conv_to_bytes();
-
-                    self.ncmmsgtxbuf[0x0000..0x000C].copy_from_slice(headervec.as_slice());
-                    self.ncmmsgtxbuf[0x000C..TOTAL_HEADER_SIZE]
-                        .copy_from_slice(datagramvec.as_slice());
-                    //TODO: this is only correct for 1 datagram
-                    self.ncmmsgtxbuf[TOTAL_HEADER_SIZE..(TOTAL_HEADER_SIZE + msg_len)]
-                        .copy_from_slice(msg[0..msg_len].as_ref());
-                    debug!(
-                        "sending the following stream {:02x}",
-                        self.ncmmsgtxbuf[0..self.usbmsgtotlen]
-                    );
-                    if self.usbmsgtotlen <= EP_DATA_BUF_SIZE {
-                        self.txstate = IpTxState::Header;
-                    } else {
-                        self.txstate = IpTxState::Sending;
-                    }
+                //copy as many frames into the ntb as fit, frames the host did not ask for are
+                //dropped. the next frame waits in txnext until it is known whether it fits.
+                let filter = self.packet_filter;
+                if self.txnext.is_none() {
+                    self.txnext = txq.pop().ok().filter(|x| filter.accepts(&x[0..6]));
                 }
-            }
-            IpTxState::Header => {
-                //send only the header.
-                let mut msg: [u8; EP_DATA_BUF_SIZE] = [0u8; EP_DATA_BUF_SIZE];
-                msg[0..TOTAL_HEADER_SIZE].clone_from_slice(&self.ncmmsgtxbuf[0..TOTAL_HEADER_SIZE]);
-                if let Ok(()) = usbtxring.push((TOTAL_HEADER_SIZE, msg)) {
-                    if self.usbmsgtotlen == TOTAL_HEADER_SIZE {
-                        self.txstate = IpTxState::Ready;
-                    } else {
-                        self.txtransactioncnt += TOTAL_HEADER_SIZE;
-                        self.txstate = IpTxState::Sending;
-                        debug!("sent header!");
+                if self.txnext.as_ref().is_some_and(|x| self.tx_fits(x.len())) {
+                    if let Some(msg) = self.txnext.take() {
+                        let msg_len = msg.len();
+                        debug!("sending {:02x}", msg[0..msg_len]);
+                        if self.txdatagram.datagrams.is_empty() {
+                            self.txstarted = get_counter();
+                        }
+                        self.txdatagram.datagrams.push(NCMDatagram {
+                            index: self.txpos as u32,
+                            length: msg_len as u32,
+                        });
+                        self.ncmmsgtxbuf[self.txpos..self.txpos + msg_len]
+                            .copy_from_slice(msg[0..msg_len].as_ref());
+                        self.txpos = align_in(self.txpos + msg_len);
                     }
                 }
+                //send once the next frame doesn't fit, or when no more frames came in time.
+                let waited = get_counter().wrapping_sub(self.txstarted);
+                if !self.txdatagram.datagrams.is_empty()
+                    && (self.txnext.is_some() || waited >= TX_FLUSH_MS)
+                {
+                    self.finish_ntb();
+                }
             }
             IpTxState::Sending => {
                 // we can only send chunks of 64 bytes, so we will incrementally walk the buffer;
@@ -313,9 +482,9 @@ impl NcmApiManager {
                 }
             }
             IpTxState::Zlp => {
-                if (self.currtxdatalen % EP_DATA_BUF_SIZE) == 0 {
+                //a transfer that fills its last packet needs a zero length one to end it.
+                if (self.usbmsgtotlen % EP_DATA_BUF_SIZE) == 0 {
                     if let Ok(()) = usbtxring.push((0, [0u8; 64])) {
-                        self.txdatagram.datagrams.clear();
                         self.txstate = IpTxState::Ready;
                         self.txtransactioncnt = 0;
                         info!("sent zlp!");
@@ -323,7 +492,6 @@ impl NcmApiManager {
                         warn!("usb tx ring is full, waiting.");
                     }
                 } else {
-                    self.txdatagram.datagrams.clear(); 
                     self.txstate = IpTxState::Ready;
                     self.txtransactioncnt = 0;
                 }
@@ -331,74 +499,91 @@ impl NcmApiManager {
         };
     
         // RX HANDLING
-        for (size, usbbuf) in usbrxring.try_iter() {
-            match self.rxstate {
-                IpRxState::AwaitHeader => {
-                    if size < core::mem::size_of::<NCMTransferHeader>() {
-                        warn!("got unaligned ncm msg");
-                        // info!("unaligned msg {:02x}", usbbuf[0..size]);
-                        continue; //dont handle partial ncm headers
-                    }
-                    // attempt to parse the start of the buffer as a transfer header (by checking the signiture is correct)
-                    self.currheader = match usbbuf[0..size].try_into().ok() {
-                        Some(x) => x,
-                        None => continue,
-                    };
-                    // info!("rx seq: {}",self.currheader.sequence);
-
-                    //start copying towards the rx buffer
-                    self.ncmmsgrxbuf[0..size].copy_from_slice(&usbbuf[0..size]);
-                    self.currcnt += size;
-
-                    //sanity check
-                    if self.currheader.blocklen > NCM_MAX_IN_SIZE as u16 {
-                        panic!("we received a message that is bigger than our buffer!");
-                    }
-
-                    self.rxstate = IpRxState::CopyEntireMsg;
+        //the ntb in the rx buffer is still being handed over, the rest waits in the usb ring.
+        let usbrxpending = if self.rxbufready { None } else { Some(usbrxring.try_iter()) };
+        for (size, usbbuf) in usbrxpending.into_iter().flatten() {
+            let packet = &usbbuf[0..size];
+            if let IpRxState::AwaitHeader = self.rxstate {
+                //anything but the start of an ntb is skipped, that's how rx gets back in step
+                //after an error.
+                let Ok(header) = TryInto::<NCMTransferHeader>::try_into(packet) else {
+                    continue;
+                };
+                if let Err(x) = self.check_header(&header) {
+                    self.drop_ntb(x);
+                    continue;
                 }
-                IpRxState::CopyEntireMsg => {
-                    let copysize = (self.currheader.blocklen as usize - self.currcnt).min(size);
-
-                    self.ncmmsgrxbuf[self.currcnt..self.currcnt + copysize]
-                        .copy_from_slice(&usbbuf[0..copysize]);
-
-                    self.currcnt += copysize;
+                if self.rxsequence.is_some_and(|x| x != header.sequence) {
+                    self.rxseqgaps += 1;
+                    let (sequence, gaps) = (header.sequence, self.rxseqgaps);
+                    warn!("ntb sequence jumped to {}, {} gaps so far", sequence, gaps);
+                }
+                self.rxsequence = Some(header.sequence.wrapping_add(1));
+                self.currheader = header;
+                self.rxstate = IpRxState::CopyEntireMsg;
+            }
 
-                    if self.currcnt == self.currheader.blocklen as usize {
-                        self.restart_rx();
-                        self.rxbufready = true
+            //start copying towards the rx buffer, whatever follows the block in the transfer is
+            //padding.
+            let blocklen = self.currheader.blocklen as usize;
+            let copysize = (blocklen - self.currcnt).min(size);
+            self.ncmmsgrxbuf[self.currcnt..self.currcnt + copysize]
+                .copy_from_slice(&packet[0..copysize]);
+            self.currcnt += copysize;
+
+            if self.currcnt == blocklen {
+                self.restart_rx();
+                match self.process_ndp() {
+                    Ok(()) => {
+                        debug!("processing {} datagrams", self.currndp.datagrams.len());
+                        self.rxbufready = true;
+                        break;
                     }
+                    Err(x) => self.drop_ntb(x),
                 }
+            } else if size < EP_DATA_BUF_SIZE {
+                //a short packet ends the transfer, this one ended before its block did.
+                self.restart_rx();
+                self.drop_ntb(NCMError::SizeError);
             }
         }
 
-        if self.rxbufready {
-            self.process_ndp();
-            const MAXSIZE: u16 = NCM_MAX_IN_SIZE as u16;
-            debug!("processing {} datagrams", self.currndp.datagrams.len());
-            self.currndp
-                .datagrams
-                .iter()
-                .for_each(|dgram| match dgram.length {
-                    0 => (),
-                    1..=MAXSIZE => {
-                        let idx_uz = dgram.index as usize;
-                        let len_uz = dgram.length as usize;
-                        let mut rxmsg: [u8; MTU] = [0u8; MTU];
-                        rxmsg[0..len_uz]
-                            .copy_from_slice(&self.ncmmsgrxbuf[idx_uz..idx_uz + len_uz]);
-                        debug!("incoming {:02x}", rxmsg[0..len_uz]);
-                        if let Err(x) = rxq.push((len_uz, rxmsg)) {
-                            match x {
-                                PushError::Full(_y) => warn!("rxq is full!"),
-                                PushError::Closed(_y) => warn!("rxq is closed!"),
-                            }
-                        };
-                    }
-                    _ => panic!("Somehow we received a packet that is too big."),
-                });
-            self.rxbufready = false;
+        //an ntb can carry more datagrams than rxq holds, they go up as it makes room.
+        while self.rxbufready && !rxq.is_full() {
+            let Some(dgram) = self.currndp.datagrams.get(self.currdgram) else {
+                self.rxbufready = false;
+                break;
+            };
+            match dgram.length as usize {
+                0 => (),
+                1..=MAX_FRAME_SIZE => {
+                    //the frame waits in the ntb until a buffer is free.
+                    let Some(mut rxmsg) = PacketBuf::alloc() else {
+                        break;
+                    };
+                    let idx_uz = dgram.index as usize;
+                    let len_uz = dgram.length as usize;
+                    rxmsg.truncate(len_uz);
+                    rxmsg.copy_from_slice(&self.ncmmsgrxbuf[idx_uz..idx_uz + len_uz]);
+                    debug!("incoming {:02x}", rxmsg[0..len_uz]);
+                    if let Err(x) = rxq.push(rxmsg) {
+                        match x {
+                            PushError::Full(_y) => warn!("rxq is full!"),
+                            PushError::Closed(_y) => warn!("rxq is closed!"),
+                        }
+                    };
+                }
+                len_uz => {
+                    self.rxdropped += 1;
+                    warn!("dropped a {} byte frame, larger than the mtu", len_uz);
+                }
+            }
+            self.currdgram += 1;
         }
     }
 }
+
+//datagrams start on ndp_in_divisor boundaries, with no remainder.
+fn align_in(pos: usize) -> usize {
+    pos.next_multiple_of(NDP_IN_DIVISOR)
+}
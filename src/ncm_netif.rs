@@ -1,14 +1,79 @@
 
+use core::cell::RefCell;
+use core::ops::{Deref, DerefMut};
+use core::ptr::addr_of_mut;
+use critical_section::{with, Mutex};
 use defmt::warn;
 use smoltcp::phy::{self, DeviceCapabilities, Medium};
 use smoltcp::time::Instant;
-use smoltcp::wire::IPV4_MIN_MTU;
+use smoltcp::wire::{ETHERNET_HEADER_LEN, IPV4_MIN_MTU};
 extern crate alloc;
 use concurrent_queue::ConcurrentQueue;
-pub const MTU: usize = IPV4_MIN_MTU;
+// the ip mtu, anything from IPV4_MIN_MTU up to 1500. the ncm descriptor and dhcp (option 26)
+// hand the same value to the host.
+pub const MTU: usize = 1500;
+// an ethernet frame carrying MTU bytes, without the fcs.
+pub const MAX_FRAME_SIZE: usize = MTU + ETHERNET_HEADER_LEN;
+const _: () = assert!(MTU >= IPV4_MIN_MTU && MTU <= 1500);
 const MAX_QUEUE_SIZE: usize = 1;
+// frame buffers shared by both queues. on top of the queued frames, smoltcp holds a received
+// one while it fills another to answer it, and ncm_api holds the next one to send until it knows
+// whether it fits the ntb being filled.
+const POOL_SIZE: usize = 2 * MAX_QUEUE_SIZE + 2;
 
-pub type Ethmsg = (usize,[u8;MTU]);
+static mut FRAMES: [[u8; MAX_FRAME_SIZE]; POOL_SIZE] = [[0; MAX_FRAME_SIZE]; POOL_SIZE];
+// a bit per buffer in FRAMES that is handed out.
+static FRAMES_USED: Mutex<RefCell<u32>> = Mutex::new(RefCell::new(0));
+
+// a frame in one of the pool's buffers, the buffer goes back to the pool when it's dropped.
+pub struct PacketBuf {
+    index: usize,
+    len: usize,
+}
+
+impl PacketBuf {
+    // a free buffer, as a full sized frame.
+    pub fn alloc() -> Option<PacketBuf> {
+        let index = with(|cs| {
+            let mut used = FRAMES_USED.borrow(cs).borrow_mut();
+            let index = (0..POOL_SIZE).find(|x| *used & (1 << x) == 0)?;
+            *used |= 1 << index;
+            Some(index)
+        })?;
+        Some(PacketBuf {
+            index,
+            len: MAX_FRAME_SIZE,
+        })
+    }
+
+    pub fn truncate(&mut self, len: usize) {
+        self.len = self.len.min(len);
+    }
+}
+
+impl Deref for PacketBuf {
+    type Target = [u8];
+    fn deref(&self) -> &[u8] {
+        // the buffer at index belongs to this PacketBuf alone until it's dropped.
+        let frame = unsafe { addr_of_mut!(FRAMES[self.index]) } as *const u8;
+        unsafe { core::slice::from_raw_parts(frame, self.len) }
+    }
+}
+
+impl DerefMut for PacketBuf {
+    fn deref_mut(&mut self) -> &mut [u8] {
+        let frame = unsafe { addr_of_mut!(FRAMES[self.index]) } as *mut u8;
+        unsafe { core::slice::from_raw_parts_mut(frame, self.len) }
+    }
+}
+
+impl Drop for PacketBuf {
+    fn drop(&mut self) {
+        with(|cs| *FRAMES_USED.borrow(cs).borrow_mut() &= !(1 << self.index));
+    }
+}
+
+pub type Ethmsg = PacketBuf;
 pub type  EthRingBuffers<'a> = (&'a mut ConcurrentQueue<Ethmsg>,&'a mut ConcurrentQueue<Ethmsg>);
 
 pub struct StmPhy {
@@ -31,21 +96,24 @@ impl phy::Device for StmPhy {
 
     fn receive(&mut self, _timestamp: Instant) -> Option<(Self::RxToken<'_>, Self::TxToken<'_>)> {
         if !self.rxq.is_empty(){
-           return Some((StmPhyRxToken(&mut self.rxq), StmPhyTxToken(&mut self.txq)))
+           // the answer to the frame needs a buffer of its own.
+           let buf = PacketBuf::alloc()?;
+           return Some((StmPhyRxToken(&mut self.rxq), StmPhyTxToken(&mut self.txq, buf)))
         }
         None
     }
 
     fn transmit(&mut self, _timestamp: Instant) -> Option<Self::TxToken<'_>> {
         if !self.txq.is_full(){
-            return Some(StmPhyTxToken(&mut self.txq));
+            return Some(StmPhyTxToken(&mut self.txq, PacketBuf::alloc()?));
         }
         None
     }
 
     fn capabilities(&self) -> DeviceCapabilities {
         let mut caps = DeviceCapabilities::default();
-        caps.max_transmission_unit = MTU;
+        // for ethernet, smoltcp counts the header in.
+        caps.max_transmission_unit = MAX_FRAME_SIZE;
         caps.max_burst_size = Some(1);
         caps.medium = Medium::Ethernet;
         caps
@@ -60,27 +128,28 @@ impl<'a> phy::RxToken for StmPhyRxToken<'a> {
         F: FnOnce(&mut [u8]) -> R,
     {
         if let Ok(mut x) = self.0.pop(){
-            let result: R = f(&mut x.1);
+            let result: R = f(&mut x);
             result
         }
         else{
             panic!("RX token called but queue was empty");
         }
-        
-       
+
+
     }
 }
 
-pub struct StmPhyTxToken<'a>(&'a mut ConcurrentQueue<Ethmsg>);
+pub struct StmPhyTxToken<'a>(&'a mut ConcurrentQueue<Ethmsg>, PacketBuf);
 
 impl<'a> phy::TxToken for StmPhyTxToken<'a> {
     fn consume<R, F>(self, len: usize, f: F) -> R
     where
         F: FnOnce(&mut [u8]) -> R,
     {
-        let mut output = [0u8;MTU];
-        let result = f(&mut output[0..len]);
-        if let Err(_x) =self.0.push((len,output)) {
+        let mut output = self.1;
+        output.truncate(len);
+        let result = f(&mut output);
+        if let Err(_x) =self.0.push(output) {
             warn!("overloaded ethernet tx buf, dropped packet!");
         }
         //update buffer with new pending packet
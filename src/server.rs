@@ -1,4 +1,6 @@
 extern crate alloc;
+use alloc::boxed::Box;
+use alloc::string::{String, ToString};
 use alloc::vec::Vec;
 use alloc::{format, vec};
 
@@ -9,203 +11,629 @@ use smoltcp::socket::tcp::State;
 use smoltcp::socket::udp;
 use smoltcp::time::Instant;
 use smoltcp::wire::EthernetAddress;
-use smoltcp::wire::{IpAddress, IpCidr, Ipv4Address};
+use smoltcp::wire::{IpAddress, IpCidr, Ipv4Address, Ipv4Cidr};
 
 use crate::get_stats;
-use crate::set_rgb;
-use crate::ncm_netif::{EthRingBuffers, StmPhy};
+use crate::{get_heap_usage, get_link_up, get_uptime};
+use crate::{set_auth_token, take_auth_token};
+use crate::{set_net_config, take_net_config};
+use crate::{get_rgb, set_rgb};
+use crate::ncm_netif::{EthRingBuffers, StmPhy, MTU};
 
 use defmt::info;
 
 use crate::http::{
-    gen_http_header, CallbackBt, HttpCallback, HttpContentType, HttpEncodingType, HttpError,
-    HttpRequest, Httpserver, HTTP_404_RESPONSE,
+    HttpBody, HttpContentType, HttpError, HttpParser, HttpRequest, HttpResponse, HttpStatus,
+    HttpStream, Httpserver, LongLived, Router,
 };
 
 use crate::dhcp::{
-    DHCP_SERVER_PORT,DHCP_CLIENT_PORT,DhcpServer
+    DHCP_SERVER_PORT,DHCP_CLIENT_PORT,DhcpConfig,DhcpServer
 };
+use crate::assets;
+use crate::dns::{DnsServer, DNS_SERVER_PORT};
+use crate::events::EventStream;
+use crate::json::JsonValue;
+use crate::kvstore::{KvStore, StoreKey};
+use crate::l412flash::L412Flash;
+use crate::websocket::{self, WebSocket, WebSocketHandler, WsMessage};
+use crate::mdns::{MdnsResponder, MDNS_GROUP, MDNS_PORT};
+
+// RFC 8908: tell the host it is behind a portal, so it opens our ui.
+fn captive_portal(_request: &HttpRequest) -> Result<HttpResponse, HttpError> {
+    let data = format!("{{\"captive\":true,\"user-portal-url\":\"http://{HOSTNAME}.{DOMAIN}/\"}}");
+    Ok(HttpResponse::ok(HttpContentType::CaptivePortal, data))
+}
 
-struct HttpGetHandle;
-
-impl HttpCallback for HttpGetHandle {
-    fn handle_request(&self, request: &HttpRequest) -> Vec<u8> {
-        match request.path.as_str() {
-            "/" | "/index.html" => {
-                let data  = include_bytes!("../static/mockup_mini.html.gz");
-                let mut buf: Vec<u8> = gen_http_header(
-                    Some(data),
-                    HttpContentType::Text,
-                    Some(HttpEncodingType::Gzip),
-                );
-                buf.extend_from_slice(data);
-                buf
-            }
-            "/js_chart_mini.js" => {
-                let data = include_bytes!("../static/js_chart_mini.js.gz");
-                let mut buf: Vec<u8> = gen_http_header(
-                    Some(data),
-                    HttpContentType::Script,
-                    Some(HttpEncodingType::Gzip),
-                );
-                buf.extend_from_slice(data);
-                buf
-            }
-            "/stats" => {
-                let stats = get_stats();
-                let data = &format!("{},{}", stats.0,stats.1).into_bytes();
-                let mut buf = gen_http_header(Some(data), HttpContentType::Data, None);
-                buf.extend_from_slice(data);
-                buf
+fn stats(_request: &HttpRequest) -> Result<HttpResponse, HttpError> {
+    let stats = get_stats();
+    let data = format!("{},{}", stats.0, stats.1);
+    Ok(HttpResponse::ok(HttpContentType::Data, data))
+}
+
+fn hex_byte(hex: &str) -> Result<u8, HttpError> {
+    if hex.len() != 2 {
+        return Err(HttpError::ParseError);
+    }
+    u8::from_str_radix(hex, 16).map_err(|_| HttpError::ParseError)
+}
+
+// a colour as "#rrggbb".
+fn parse_rgb(text: &str) -> Result<(u8, u8, u8), HttpError> {
+    let hex = text
+        .strip_prefix('#')
+        .filter(|x| x.len() == 6 && x.is_ascii())
+        .ok_or(HttpError::ParseError)?;
+    Ok((hex_byte(&hex[0..2])?, hex_byte(&hex[2..4])?, hex_byte(&hex[4..6])?))
+}
+
+fn rgb(request: &HttpRequest) -> Result<HttpResponse, HttpError> {
+    let (r, g, b) = parse_rgb(&request.body)?;
+    info!("r:{} g:{} b:{}",r,g,b);
+    set_rgb((r, g, b));
+    Ok(HttpResponse::ok(HttpContentType::Text, b""))
+}
+
+// every text message is a colour as "#rrggbb", so the picker can be dragged around.
+struct LedSocket;
+
+impl WebSocketHandler for LedSocket {
+    fn on_message(&mut self, message: WsMessage) -> Option<WsMessage> {
+        if let WsMessage::Text(text) = message {
+            match parse_rgb(&text) {
+                Ok(rgb) => set_rgb(rgb),
+                Err(_) => warn!("bad colour over websocket"),
             }
-            _ => HTTP_404_RESPONSE.into(),
         }
+        None
     }
 }
 
-const HTTPGETHANDLE: HttpGetHandle = HttpGetHandle;
-const HTTPPOSTHANDLE: HttpPostHandle = HttpPostHandle;
+fn led_socket(request: &HttpRequest) -> Result<HttpResponse, HttpError> {
+    websocket::accept(request, Box::new(LedSocket))
+}
+
+// sets one channel of the led, the body is its value as "rr".
+fn led_channel(request: &HttpRequest) -> Result<HttpResponse, HttpError> {
+    let value = hex_byte(request.body.trim())?;
+    let (mut r, mut g, mut b) = get_rgb();
+    match request.param("channel") {
+        Some("r" | "red") => r = value,
+        Some("g" | "green") => g = value,
+        Some("b" | "blue") => b = value,
+        _ => return Ok(HttpResponse::not_found()),
+    }
+    set_rgb((r, g, b));
+    Ok(HttpResponse::ok(HttpContentType::Text, b""))
+}
+
+fn led_state() -> JsonValue {
+    let (r, g, b) = get_rgb();
+    JsonValue::object(vec![("r", r.into()), ("g", g.into()), ("b", b.into())])
+}
+
+fn api_status(_request: &HttpRequest) -> Result<HttpResponse, HttpError> {
+    let (lps, temp) = get_stats();
+    let (heap_used, heap_free) = get_heap_usage();
+    let value = JsonValue::object(vec![
+        // seconds fit an i32 for 68 years, milliseconds wouldn't for a month.
+        ("uptime_s", JsonValue::Number((get_uptime() / 1000) as i32)),
+        ("loops_per_second", JsonValue::Number(lps as i32)),
+        ("temperature_c", JsonValue::Hundredths(temp as i32)),
+        ("led", led_state()),
+        ("link_up", get_link_up().into()),
+        (
+            "heap",
+            JsonValue::object(vec![
+                ("used", JsonValue::Number(heap_used as i32)),
+                ("free", JsonValue::Number(heap_free as i32)),
+            ]),
+        ),
+    ]);
+    Ok(HttpResponse::json(HttpStatus::Ok, &value))
+}
+
+fn api_led(_request: &HttpRequest) -> Result<HttpResponse, HttpError> {
+    Ok(HttpResponse::json(HttpStatus::Ok, &led_state()))
+}
+
+// the body is an object with any of "r", "g" and "b" as 0-255, the others are left alone.
+fn api_set_led(request: &HttpRequest) -> Result<HttpResponse, HttpError> {
+    let body = request.json()?;
+    if !matches!(body, JsonValue::Object(_)) {
+        return Err(HttpError::ParseError);
+    }
+    let (mut r, mut g, mut b) = get_rgb();
+    for (name, channel) in [("r", &mut r), ("g", &mut g), ("b", &mut b)] {
+        if let Some(value) = body.get(name) {
+            *channel = value
+                .as_i32()
+                .and_then(|x| u8::try_from(x).ok())
+                .ok_or(HttpError::ParseError)?;
+        }
+    }
+    set_rgb((r, g, b));
+    api_led(request)
+}
+
+// a sample as published to the event stream, on every new perfcounter reading.
+pub fn telemetry() -> String {
+    let (lps, temp) = get_stats();
+    let value = JsonValue::object(vec![
+        ("loops_per_second", JsonValue::Number(lps as i32)),
+        ("temperature_c", JsonValue::Hundredths(temp as i32)),
+        ("led", led_state()),
+    ]);
+    value.to_string()
+}
+
+// keeps the connection open and pushes every telemetry sample, for EventSource in the ui.
+fn events(_request: &HttpRequest) -> Result<HttpResponse, HttpError> {
+    let body = HttpBody::Generator(Box::new(EventStream::default()));
+    Ok(HttpResponse::ok(HttpContentType::EventStream, body)
+        .with_header("Cache-Control", "no-cache".into()))
+}
+
+// the body is {"token":"..."}, up to 64 printable ascii characters. an empty token turns
+// authentication off. it is saved to the config store by eth_task.
+fn api_set_auth(request: &HttpRequest) -> Result<HttpResponse, HttpError> {
+    let body = request.json()?;
+    let Some(JsonValue::String(token)) = body.get("token") else {
+        return Err(HttpError::ParseError);
+    };
+    if token.len() > 64 || !token.bytes().all(|x| x.is_ascii_graphic()) {
+        return Err(HttpError::ParseError);
+    }
+    set_auth_token(token.clone());
+    Ok(HttpResponse::ok(HttpContentType::Json, b"{}"))
+}
+
+// "a.b.c.d".
+fn parse_ipv4(text: &str) -> Result<[u8; 4], HttpError> {
+    let mut ip = [0u8; 4];
+    let mut parts = text.split('.');
+    for byte in ip.iter_mut() {
+        let part = parts.next().ok_or(HttpError::ParseError)?;
+        *byte = part.parse().map_err(|_| HttpError::ParseError)?;
+    }
+    match parts.next() {
+        Some(_) => Err(HttpError::ParseError),
+        None => Ok(ip),
+    }
+}
+
+// the body is {"ip":"a.b.c.d","prefix_len":0-30,"pool_start":0-255,"pool_end":0-255}, the pool
+// being the last byte of the addresses dhcp hands out. it is saved to the config store by
+// eth_task and used from the next reset on.
+fn api_set_net(request: &HttpRequest) -> Result<HttpResponse, HttpError> {
+    let body = request.json()?;
+    let Some(JsonValue::String(ip)) = body.get("ip") else {
+        return Err(HttpError::ParseError);
+    };
+    // laid out as stored, so it is held to what a stored config has to be. the mac stays,
+    // eth_task fills it in.
+    let mut buf = [0u8; NETCONFIG_LEN];
+    buf[0..4].copy_from_slice(&parse_ipv4(ip)?);
+    for (idx, name) in [(4, "prefix_len"), (11, "pool_start"), (12, "pool_end")] {
+        buf[idx] = body
+            .get(name)
+            .and_then(JsonValue::as_i32)
+            .and_then(|x| u8::try_from(x).ok())
+            .ok_or(HttpError::ParseError)?;
+    }
+    set_net_config(NetConfig::from_bytes(&buf).ok_or(HttpError::ParseError)?);
+    Ok(HttpResponse::ok(HttpContentType::Json, b"{}"))
+}
+
+fn register_routes(router: &mut Router) {
+    router.public_route("GET", CAPTIVE_PORTAL_PATH, captive_portal);
+    router.public_route("GET", "/stats", stats);
+    router.public_route("GET", "/events", events);
+    router.route("POST", "/rgb", rgb);
+    router.route("POST", "/led/{channel}", led_channel);
+    router.route("GET", "/ws/led", led_socket);
+    router.public_route("GET", "/api/v1/status", api_status);
+    router.public_route("GET", "/api/v1/led", api_led);
+    router.route("PUT", "/api/v1/led", api_set_led);
+    router.route("PUT", "/api/v1/auth", api_set_auth);
+    router.route("PUT", "/api/v1/net", api_set_net);
+    router.fallback(assets::serve);
+}
 
 const RINGBUFSIZE: usize = 128;
+// listening sockets on port 80, so a browser can fetch the page and its assets in parallel,
+// while the event stream and the led websocket each hold one open. only one of each is kept,
+// a newer one closes the older, so a second tab doesn't leave the api without a socket.
+const HTTP_SOCKETS: usize = 4;
+// the web server takes on a request, or makes the next piece of a response, only while this
+// much heap is free: enough to parse the largest request and answer it, or to inflate one asset
+// segment. until then received bytes wait in the socket. see init_heap for the rest of it.
+const HTTP_HEAP_RESERVE: usize = 3072;
+// a kept-alive connection with nothing going on is closed after this long.
+const HTTP_IDLE_TIMEOUT_MS: u32 = 5000;
+const HOSTNAME: &str = "stamdev";
+const DOMAIN: &str = "lan";
+// when set the board is handed out as the host's gateway and dns server, otherwise the host
+// only learns a route to the board's subnet and keeps its own internet connection.
+const DEFAULT_ROUTE: bool = true;
+const CAPTIVE_PORTAL_PATH: &str = "/captive-portal";
+// handed out in dhcp option 114, by name as RFC 8910 asks, so it needs our dns server: the
+// HOSTNAME.DOMAIN of it and CAPTIVE_PORTAL_PATH. RFC 8908 wants https, so only hosts that
+// accept a plain http portal use it.
+const CAPTIVE_PORTAL_URI: &str = "http://stamdev.lan/captive-portal";
+
+// network settings, read from the config store at boot. the defaults apply until one is saved
+// with PUT /api/v1/net.
+#[derive(Clone, Copy, defmt::Format)]
+pub struct NetConfig {
+    pub ip: Ipv4Address,
+    pub prefix_len: u8,
+    pub mac: [u8; 6],
+    // first and last host address handed out by dhcp.
+    pub pool_start: u8,
+    pub pool_end: u8,
+}
 
-struct HttpPostHandle;
-
-impl HttpCallback for HttpPostHandle {
-    fn handle_request(&self, request: &HttpRequest) -> Vec<u8> {
-        match request.path.as_str() {
-            "/rgb" => {
-                let r = u8::from_str_radix(&request.body[1..3], 16).unwrap();
-                let g = u8::from_str_radix(&request.body[3..5], 16).unwrap();
-                let b = u8::from_str_radix(&request.body[5..7], 16).unwrap();
-                info!("r:{} g:{} b:{}",r,g,b);
-                set_rgb((r, g, b));
-                gen_http_header(None, HttpContentType::Text, None)
-            },
-            _ => HTTP_404_RESPONSE.into(),
+impl Default for NetConfig {
+    fn default() -> Self {
+        NetConfig {
+            ip: Ipv4Address::new(192, 168, 69, 1),
+            prefix_len: 24,
+            mac: [0x00, 0x80, 0xE1, 0x00, 0x00, 0x01],
+            pool_start: 5,
+            pool_end: 128,
+        }
+    }
+}
+
+const NETCONFIG_LEN: usize = 13;
+
+impl NetConfig {
+    // our address and both ends of the pool have to be hosts of the subnet, neither its network
+    // nor its broadcast address.
+    pub fn from_bytes(buf: &[u8]) -> Option<Self> {
+        if buf.len() != NETCONFIG_LEN || buf[4] > 30 || buf[11] > buf[12] {
+            return None;
+        }
+        let config = NetConfig {
+            ip: Ipv4Address::from_bytes(&buf[0..4]),
+            prefix_len: buf[4],
+            mac: buf[5..11].try_into().unwrap(),
+            pool_start: buf[11],
+            pool_end: buf[12],
+        };
+        [buf[3], buf[11], buf[12]]
+            .into_iter()
+            .all(|x| config.is_host(x))
+            .then_some(config)
+    }
+
+    // whether our address with `last` as its last byte is a host of the subnet.
+    fn is_host(&self, last: u8) -> bool {
+        let mask = !(u32::MAX >> self.prefix_len);
+        let ip = u32::from_be_bytes(self.ip.0);
+        let addr = ip & !0xff | last as u32;
+        (addr ^ ip) & mask == 0 && addr & !mask != 0 && addr | mask != u32::MAX
+    }
+
+    pub fn to_bytes(self) -> Vec<u8> {
+        let mut buf = Vec::<u8>::with_capacity(NETCONFIG_LEN);
+        buf.extend_from_slice(self.ip.as_bytes());
+        buf.push(self.prefix_len);
+        buf.extend_from_slice(&self.mac);
+        buf.push(self.pool_start);
+        buf.push(self.pool_end);
+        buf
+    }
+}
+
+// per connection state of one of the http sockets.
+struct HttpConnection {
+    handle: SocketHandle,
+    parser: HttpParser,
+    // the response being sent.
+    tx: Option<HttpStream>,
+    // close once tx is out.
+    close_after_send: bool,
+    // eth_task time of the last request or response, None while listening.
+    last_active: Option<u32>,
+    // set once the connection was upgraded, it carries websocket frames from then on.
+    websocket: Option<WebSocket>,
+    // what the connection is kept open for, it isn't closed when idle.
+    long_lived: Option<LongLived>,
+}
+
+impl HttpConnection {
+    fn new(handle: SocketHandle) -> Self {
+        HttpConnection {
+            handle,
+            parser: HttpParser::default(),
+            tx: None,
+            close_after_send: false,
+            last_active: None,
+            websocket: None,
+            long_lived: None,
         }
     }
+
+    fn reset(&mut self) {
+        self.parser.reset();
+        self.tx = None;
+        self.close_after_send = false;
+        self.last_active = None;
+        self.websocket = None;
+        self.long_lived = None;
+    }
 }
 
 pub struct TcpServer<'a> {
     device: StmPhy,
     iface: Interface,
     sockets: SocketSet<'a>,
-    tcp1_handle: SocketHandle,
+    httpconns: Vec<HttpConnection>,
     udp_handle:SocketHandle,
-    rxbytes: Vec<u8>,
+    dns_handle: SocketHandle,
+    mdns_handle: SocketHandle,
     httpserver: Httpserver,
     dhcpserver: DhcpServer,
-    msgtosend: Vec<u8>,
+    dnsserver: DnsServer,
+    mdnsresponder: MdnsResponder,
+    store: KvStore<L412Flash>,
+    netconfig: NetConfig,
 }
 
 impl<'a> TcpServer<'a> {
-    pub fn init_server(seed: u32) -> Self {
+    pub fn init_server(seed: u32, store: KvStore<L412Flash>) -> Self {
+        let netconfig = match store.get(StoreKey::NetConfig) {
+            Some(buf) => NetConfig::from_bytes(&buf).unwrap_or_else(|| {
+                warn!("stored network config is invalid, using defaults");
+                NetConfig::default()
+            }),
+            None => NetConfig::default(),
+        };
+        info!("network config: {}", netconfig);
+
         // Create interface
         let mut device = StmPhy::new();
-        let mut config = Config::new(EthernetAddress([0x00, 0x80, 0xE1, 0x00, 0x00, 0x01]).into());
+        let mut config = Config::new(EthernetAddress(netconfig.mac).into());
         config.random_seed = seed as u64;
         let mut iface = Interface::new(config, &mut device, Instant::from_millis(0));
         iface.update_ip_addrs(|ip_addrs| {
             ip_addrs
-                .push(IpCidr::new(IpAddress::v4(192, 168, 69, 1), 24))
+                .push(IpCidr::new(IpAddress::Ipv4(netconfig.ip), netconfig.prefix_len))
                 .unwrap();
         });
-        iface
-            .routes_mut()
-            .add_default_ipv4_route(Ipv4Address::new(192, 168, 69, 100))
-            .unwrap();
+        if DEFAULT_ROUTE {
+            let mut gateway = netconfig.ip;
+            gateway.0[3] = 100;
+            iface
+                .routes_mut()
+                .add_default_ipv4_route(gateway)
+                .unwrap();
+        }
 
         // Create sockets
-        let tcp1_rx_buffer = tcp::SocketBuffer::new(vec![0; 128]);
-        let tcp1_tx_buffer = tcp::SocketBuffer::new(vec![0; 128]);
-        let tcp1_socket = tcp::Socket::new(tcp1_rx_buffer, tcp1_tx_buffer);
-
+        // clients may send dhcp messages of up to 576 bytes (RFC 2131 2).
         let udp_rx_buffer = udp::PacketBuffer::new(
             vec![udp::PacketMetadata::EMPTY, udp::PacketMetadata::EMPTY],
-            vec![0; 380],
+            vec![0; 576],
         );
         let udp_tx_buffer = udp::PacketBuffer::new(
             vec![udp::PacketMetadata::EMPTY, udp::PacketMetadata::EMPTY],
-            vec![0; 380],
+            vec![0; 576],
         );
         let udp_socket = udp::Socket::new(udp_rx_buffer, udp_tx_buffer);
 
+        let dns_rx_buffer = udp::PacketBuffer::new(
+            vec![udp::PacketMetadata::EMPTY, udp::PacketMetadata::EMPTY],
+            vec![0; 256],
+        );
+        let dns_tx_buffer = udp::PacketBuffer::new(
+            vec![udp::PacketMetadata::EMPTY, udp::PacketMetadata::EMPTY],
+            vec![0; 256],
+        );
+        let dns_socket = udp::Socket::new(dns_rx_buffer, dns_tx_buffer);
+
+        let mdns_rx_buffer = udp::PacketBuffer::new(
+            vec![udp::PacketMetadata::EMPTY, udp::PacketMetadata::EMPTY],
+            vec![0; 380],
+        );
+        let mdns_tx_buffer = udp::PacketBuffer::new(
+            vec![udp::PacketMetadata::EMPTY, udp::PacketMetadata::EMPTY],
+            vec![0; 380],
+        );
+        let mut mdns_socket = udp::Socket::new(mdns_rx_buffer, mdns_tx_buffer);
+        // RFC 6762 11: responses are only trusted when sent with a hop limit of 255.
+        mdns_socket.set_hop_limit(Some(255));
+        iface
+            .join_multicast_group(&mut device, MDNS_GROUP, Instant::from_millis(0))
+            .unwrap();
 
-        let mut sockets = SocketSet::new(vec![]);
-        let tcp1_handle = sockets.add(tcp1_socket);
+        // sized up front, growing it would hold the old and the new storage at once.
+        let mut sockets = SocketSet::new(Vec::with_capacity(HTTP_SOCKETS + 3));
+        let httpconns: Vec<HttpConnection> = (0..HTTP_SOCKETS)
+            .map(|_| {
+                let rx_buffer = tcp::SocketBuffer::new(vec![0; 256]);
+                let tx_buffer = tcp::SocketBuffer::new(vec![0; 768]);
+                HttpConnection::new(sockets.add(tcp::Socket::new(rx_buffer, tx_buffer)))
+            })
+            .collect();
         let udp_handle = sockets.add(udp_socket);
+        let dns_handle = sockets.add(dns_socket);
+        let mdns_handle = sockets.add(mdns_socket);
 
         //build http server
-        let callbacks: CallbackBt = vec![&HTTPGETHANDLE,&HTTPPOSTHANDLE];
+        let mut router = Router::default();
+        register_routes(&mut router);
 
 
 
         //build the dhcp server
-        let dhcpserver = DhcpServer{
-            addrstart: 5,
-            maxaddr: 128,
+        let mut dhcpserver = DhcpServer{
+            addrstart: netconfig.pool_start,
+            maxaddr: netconfig.pool_end,
             addrcnt: 0,
             serverip: iface.ipv4_addr().unwrap(),
-            subnet: Ipv4Address::new(255, 255, 255, 0),
+            subnet: Ipv4Cidr::new(netconfig.ip, netconfig.prefix_len).netmask(),
+            config: DhcpConfig {
+                captive_portal: DEFAULT_ROUTE.then_some(CAPTIVE_PORTAL_URI),
+                mtu: Some(MTU as u16),
+                ..if DEFAULT_ROUTE {
+                    DhcpConfig::default()
+                } else {
+                    DhcpConfig::no_default_route()
+                }
+            },
             ..DhcpServer::default()
         };
+        if let Some(buf) = store.get(StoreKey::DhcpLeases) {
+            dhcpserver.restore_leases(&buf, 0);
+        }
+
+        //build the dns server
+        let dnsserver = DnsServer {
+            hostname: format!("{HOSTNAME}.{DOMAIN}"),
+            serverip: iface.ipv4_addr().unwrap(),
+        };
+
+        let mdnsresponder = MdnsResponder {
+            hostname: HOSTNAME.into(),
+            serverip: iface.ipv4_addr().unwrap(),
+            http_port: 80,
+        };
+
+        let mut httpserver = Httpserver::new(router);
+        if let Some(token) = store.get(StoreKey::AuthToken) {
+            httpserver.set_token(core::str::from_utf8(&token).unwrap_or_default());
+        }
 
         TcpServer {
             device,
             iface,
             sockets,
-            tcp1_handle,
+            httpconns,
             udp_handle,
-            httpserver: Httpserver::new(callbacks),
+            dns_handle,
+            mdns_handle,
+            httpserver,
             dhcpserver,
-            rxbytes: Vec::<u8>::new(),
-            msgtosend: Vec::<u8>::new(),
+            dnsserver,
+            mdnsresponder,
+            store,
+            netconfig,
         }
     }
 
-    fn run_webserver(&mut self) {
-        //get the tcp socket
-        let sock = self.sockets.get_mut::<tcp::Socket>(self.tcp1_handle);
-
-        //ensure socket is open.
-        if !sock.is_open() {
-            sock.listen(80).unwrap();
-        }
-
-        // if socket was closed, reset the write pointer.
-        if sock.state() == State::CloseWait {
-            sock.close()
-        }
-
-        if sock.can_send() && !self.msgtosend.is_empty() {
-            let sent = sock
-                .send_slice(&self.msgtosend[0..])
-                .expect("failed to send message");
-            self.msgtosend = self.msgtosend[sent..].to_vec();
-        }
+    fn run_webserver(&mut self, currtime: u32) {
+        let mut opened = [None; HTTP_SOCKETS];
+        for (i, conn) in self.httpconns.iter_mut().enumerate() {
+            let sock = self.sockets.get_mut::<tcp::Socket>(conn.handle);
+
+            //ensure socket is open.
+            if !sock.is_open() {
+                // whatever the last connection left half sent belongs to nobody now.
+                conn.reset();
+                sock.listen(80).unwrap();
+                continue;
+            }
 
-        if sock.can_recv() && self.msgtosend.is_empty() {
-            let mut rxslice = [0u8; RINGBUFSIZE];
-            let len = sock.recv_slice(&mut rxslice).expect("failed to receive");
+            // if socket was closed, reset the write pointer.
+            if sock.state() == State::CloseWait {
+                sock.close()
+            }
 
-            self.rxbytes.extend_from_slice(&rxslice[0..len]);
+            if !sock.is_active() || sock.state() == State::Listen {
+                continue;
+            }
+            let last_active = *conn.last_active.get_or_insert(currtime);
+            if conn.tx.is_none()
+                && conn.long_lived.is_none()
+                && currtime.wrapping_sub(last_active) >= HTTP_IDLE_TIMEOUT_MS
+            {
+                sock.close();
+                continue;
+            }
 
-            match self.httpserver.parse_request(&self.rxbytes) {
-                Ok(resp) => {
-                    self.msgtosend = resp;
-                    //the assumption here as if we didn't parse the header we don't have all of it.
-                    self.rxbytes.clear();
+            let heap_free = get_heap_usage().1 >= HTTP_HEAP_RESERVE;
+            if let Some(tx) = conn
+                .tx
+                .as_mut()
+                .filter(|x| sock.can_send() && (heap_free || !x.generating()))
+            {
+                // the response is written straight into the socket's tx buffer.
+                let sent = sock
+                    .send(|buf| {
+                        let len = tx.write(buf);
+                        (len, len)
+                    })
+                    .expect("failed to send message");
+                if sent > 0 {
+                    conn.last_active = Some(currtime);
                 }
-                Err(x) => {
-                    if let HttpError::Unsupported = x {
-                        warn!("failed to parse request!")
+                if tx.is_done() {
+                    conn.tx = None;
+                    if conn.close_after_send {
+                        sock.close();
                     }
                 }
+            }
+
+            if let Some(ws) = conn.websocket.as_mut().filter(|_| conn.tx.is_none()) {
+                let mut out = Vec::new();
+                if sock.can_recv() && heap_free {
+                    let mut rxslice = [0u8; RINGBUFSIZE];
+                    let len = sock.recv_slice(&mut rxslice).expect("failed to receive");
+                    ws.receive(&rxslice[0..len], &mut out);
+                }
+                ws.poll(&mut out);
+                if !out.is_empty() {
+                    conn.tx = Some(HttpStream::raw(out));
+                    conn.close_after_send = ws.is_closed();
+                }
+                continue;
+            }
+
+            // a pipelined request may already be waiting in the parser.
+            if conn.tx.is_none() && heap_free && (sock.can_recv() || conn.parser.pending()) {
+                let mut rxslice = [0u8; RINGBUFSIZE];
+                let len = if sock.can_recv() {
+                    sock.recv_slice(&mut rxslice).expect("failed to receive")
+                } else {
+                    0
+                };
+
+                let peer = sock.remote_endpoint().map(|x| x.addr);
+                let reply = self
+                    .httpserver
+                    .parse_request(&mut conn.parser, &rxslice[0..len], peer);
+                if let Some(reply) = reply {
+                    conn.tx = Some(reply.stream);
+                    conn.close_after_send = !reply.keep_alive;
+                    conn.websocket = reply.upgrade.map(WebSocket::new);
+                    conn.long_lived = reply.long_lived;
+                    conn.last_active = Some(currtime);
+                    opened[i] = reply.long_lived;
+                }
+            }
+        }
+
+        // the older stream of the same kind is dropped right away, so its socket listens again.
+        for (i, kind) in opened.into_iter().enumerate() {
+            let Some(kind) = kind else {
+                continue;
             };
+            for (j, conn) in self.httpconns.iter_mut().enumerate() {
+                if j != i && conn.long_lived == Some(kind) {
+                    self.sockets.get_mut::<tcp::Socket>(conn.handle).abort();
+                    conn.long_lived = None;
+                }
+            }
         }
     }
 
+    // leases are timed on the uptime, eth_task's clock wraps.
     fn run_dhcpserver(&mut self) {
+        let now = get_uptime();
         let udpsock = self.sockets.get_mut::<udp::Socket>(self.udp_handle);
 
         if !udpsock.is_open(){
@@ -215,16 +643,61 @@ impl<'a> TcpServer<'a> {
 
         if let Ok((buf,mut metadata)) = udpsock.recv(){
             // info!("got msg:{:02x} len: {}",buf,buf.len());
-            if let Some(msg) = self.dhcpserver.recv(buf){
+            if let Some(reply) = self.dhcpserver.recv(buf, now){
                 metadata.endpoint.port = DHCP_CLIENT_PORT;
-                metadata.endpoint.addr = Ipv4Address::new(255, 255, 255, 255).into();
-                udpsock.send_slice(msg.as_slice(), metadata).unwrap();
+                metadata.endpoint.addr = reply.dest.into();
+                if udpsock.send_slice(reply.msg.as_slice(), metadata).is_err() {
+                    warn!("dhcp reply dropped");
+                }
+            }
+        }
+
+        if core::mem::take(&mut self.dhcpserver.leases_changed) {
+            let leases = self.dhcpserver.save_leases(now);
+            if let Err(e) = self.store.set(StoreKey::DhcpLeases, &leases) {
+                warn!("failed to save dhcp leases: {}", e);
             }
         }
 
 
 
 
+    }
+
+    fn run_dnsserver(&mut self) {
+        let udpsock = self.sockets.get_mut::<udp::Socket>(self.dns_handle);
+
+        if !udpsock.is_open() {
+            udpsock.bind(DNS_SERVER_PORT).unwrap()
+        }
+
+        if let Ok((buf, metadata)) = udpsock.recv() {
+            if let Some(msg) = self.dnsserver.recv(buf) {
+                if udpsock.send_slice(msg.as_slice(), metadata).is_err() {
+                    warn!("dns reply dropped");
+                }
+            }
+        }
+    }
+
+    fn run_mdnsresponder(&mut self) {
+        let udpsock = self.sockets.get_mut::<udp::Socket>(self.mdns_handle);
+
+        if !udpsock.is_open() {
+            udpsock.bind(MDNS_PORT).unwrap()
+        }
+
+        if let Ok((buf, mut metadata)) = udpsock.recv() {
+            let legacy = metadata.endpoint.port != MDNS_PORT;
+            if let Some(reply) = self.mdnsresponder.recv(buf, legacy) {
+                if !reply.unicast {
+                    metadata.endpoint.addr = MDNS_GROUP.into();
+                }
+                if udpsock.send_slice(reply.msg.as_slice(), metadata).is_err() {
+                    warn!("mdns reply dropped");
+                }
+            }
+        }
     }
 
     pub fn eth_task(&mut self, currtime: u32) {
@@ -234,8 +707,25 @@ impl<'a> TcpServer<'a> {
         self.iface
             .poll(timestamp, &mut self.device, &mut self.sockets);
 
-        self.run_webserver();
+        self.run_webserver(currtime);
+        if let Some(token) = take_auth_token() {
+            if let Err(e) = self.store.set(StoreKey::AuthToken, token.as_bytes()) {
+                warn!("failed to save the auth token: {}", e);
+            }
+            self.httpserver.set_token(&token);
+        }
+        if let Some(config) = take_net_config() {
+            let config = NetConfig {
+                mac: self.netconfig.mac,
+                ..config
+            };
+            if let Err(e) = self.store.set(StoreKey::NetConfig, &config.to_bytes()) {
+                warn!("failed to save the network config: {}", e);
+            }
+        }
         self.run_dhcpserver();
+        self.run_dnsserver();
+        self.run_mdnsresponder();
     }
     pub fn get_bufs(&mut self) -> EthRingBuffers {
         (&mut self.device.rxq, &mut self.device.txq)
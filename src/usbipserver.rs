@@ -1,10 +1,10 @@
 use core::mem::size_of;
-use defmt::{debug, info, warn};
+use defmt::{debug, info};
 use usb_device::class_prelude::{UsbBus, UsbBusAllocator};
 use usb_device::prelude::*;
 
 use crate::cdc_ncm::{CdcConnectionNotifyMsg, CdcSpeedChangeMsg};
-use crate::cdc_ncm::{CdcNcmClass, EP_DATA_BUF_SIZE};
+use crate::cdc_ncm::{CdcNcmClass, EthPacketFilter, NtbFormat, EP_DATA_BUF_SIZE};
 pub type Usbtransaciton = (usize, [u8; EP_DATA_BUF_SIZE]);
 use crate::cdc_ncm::{CDC_SUBCLASS_NCM, USB_CLASS_CDC};
 use concurrent_queue::ConcurrentQueue;
@@ -56,15 +56,13 @@ impl<'a, B: UsbBus> UsbIpManager<'a, B> {
         }
     }
     fn process_usb(&mut self) {
+        //while the ring is full the endpoint isn't read, so the host is NAKed until there's room.
         if !self.rxq.is_full() {
             let mut usbbuf: [u8; EP_DATA_BUF_SIZE] = [0u8; EP_DATA_BUF_SIZE];
             if let Ok(size) = self.ncm_dev.read_packet(usbbuf.as_mut_slice()) {
                 // debug!("usb buf receving {} bytes", size);
                 self.rxq.push((size, usbbuf)).unwrap();
             }
-        } else {
-            warn!("usb rx ring is full! flushing.");
-            self.rxq.try_iter().for_each(|_x| ());
         }
 
         if self.msghandled {
@@ -108,6 +106,19 @@ impl<'a, B: UsbBus> UsbIpManager<'a, B> {
         (&mut self.rxq, &mut self.txq)
     }
 
+    // the host was told the network cable is plugged in.
+    pub fn link_up(&self) -> bool {
+        matches!(self.bootstate, UsbIpBootState::Normal)
+    }
+
+    pub fn packet_filter(&self) -> EthPacketFilter {
+        self.ncm_dev.packet_filter()
+    }
+
+    pub fn ntb_format(&self) -> NtbFormat {
+        self.ncm_dev.ntb_format()
+    }
+
     fn send_speed_notificaiton(&mut self) -> usb_device::Result<usize> {
         let speedmsg: [u8; size_of::<CdcSpeedChangeMsg>()] =
             CdcSpeedChangeMsg::default().try_into().unwrap();
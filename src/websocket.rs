@@ -0,0 +1,380 @@
+//websocket
+//RFC 6455 on top of the http server. a route handler answers the upgrade request with
+//`accept`, once the 101 is out the connection carries frames to and from its handler.
+extern crate alloc;
+use alloc::boxed::Box;
+use alloc::string::String;
+use alloc::vec::Vec;
+
+use crate::http::{HttpContentType, HttpError, HttpRequest, HttpResponse, HttpStatus};
+
+const WS_GUID: &str = "258EAFA5-E914-47DA-95CA-C5AB0DC85B11";
+// larger messages close the connection with 1009, they'd have to be held in ram whole.
+const WS_MAX_MESSAGE: usize = 512;
+
+const OP_CONTINUATION: u8 = 0x0;
+const OP_TEXT: u8 = 0x1;
+const OP_BINARY: u8 = 0x2;
+const OP_CLOSE: u8 = 0x8;
+const OP_PING: u8 = 0x9;
+const OP_PONG: u8 = 0xa;
+
+// close status codes (RFC 6455 7.4.1).
+const CLOSE_PROTOCOL_ERROR: u16 = 1002;
+const CLOSE_INVALID_DATA: u16 = 1007;
+const CLOSE_TOO_BIG: u16 = 1009;
+
+pub enum WsMessage {
+    Text(String),
+    Binary(Vec<u8>),
+}
+
+pub trait WebSocketHandler {
+    // a reply, if any, is sent right away.
+    fn on_message(&mut self, message: WsMessage) -> Option<WsMessage>;
+
+    // asked on every poll, for messages the board sends on its own.
+    fn poll(&mut self) -> Option<WsMessage> {
+        None
+    }
+}
+
+// answers a websocket upgrade request, `handler` takes over the connection after it.
+pub fn accept(
+    request: &HttpRequest,
+    handler: Box<dyn WebSocketHandler>,
+) -> Result<HttpResponse, HttpError> {
+    let upgrade = request
+        .header("upgrade")
+        .is_some_and(|x| x.eq_ignore_ascii_case("websocket"));
+    let key = request
+        .header("sec-websocket-key")
+        .filter(|_| upgrade)
+        .ok_or(HttpError::ParseError)?;
+    if request.header("sec-websocket-version") != Some("13") {
+        return Ok(HttpResponse::bad_request().with_header("Sec-WebSocket-Version", "13".into()));
+    }
+
+    let mut input = Vec::from(key.trim().as_bytes());
+    input.extend_from_slice(WS_GUID.as_bytes());
+    let mut response =
+        HttpResponse::new(HttpStatus::SwitchingProtocols, HttpContentType::Plain, b"")
+            .with_header("Sec-WebSocket-Accept", base64(&sha1(&input)));
+    response.upgrade = Some(handler);
+    Ok(response)
+}
+
+struct Frame {
+    fin: bool,
+    opcode: u8,
+    // unmasked already.
+    payload: Vec<u8>,
+    // bytes the frame took up in the buffer.
+    len: usize,
+}
+
+// a connection after the handshake.
+pub struct WebSocket {
+    handler: Box<dyn WebSocketHandler>,
+    // received bytes that don't make a whole frame yet.
+    rx: Vec<u8>,
+    // the fragments of a message so far, and its opcode, 0 between messages.
+    message: Vec<u8>,
+    opcode: u8,
+    // a close frame was sent, nothing may follow it.
+    closed: bool,
+}
+
+impl WebSocket {
+    pub fn new(handler: Box<dyn WebSocketHandler>) -> Self {
+        WebSocket {
+            handler,
+            rx: Vec::new(),
+            message: Vec::new(),
+            opcode: 0,
+            closed: false,
+        }
+    }
+
+    pub fn is_closed(&self) -> bool {
+        self.closed
+    }
+
+    // takes bytes from the socket, appends whatever has to be sent back to `out`.
+    pub fn receive(&mut self, data: &[u8], out: &mut Vec<u8>) {
+        self.rx.extend_from_slice(data);
+        while !self.closed {
+            let result = match parse_frame(&self.rx) {
+                Ok(Some(frame)) => {
+                    self.rx.drain(..frame.len);
+                    self.handle(frame, out)
+                }
+                Ok(None) => break,
+                Err(code) => Err(code),
+            };
+            if let Err(code) = result {
+                self.close(code, out);
+            }
+        }
+    }
+
+    pub fn poll(&mut self, out: &mut Vec<u8>) {
+        if self.closed {
+            return;
+        }
+        if let Some(message) = self.handler.poll() {
+            write_message(message, out);
+        }
+    }
+
+    fn handle(&mut self, frame: Frame, out: &mut Vec<u8>) -> Result<(), u16> {
+        let Frame {
+            fin,
+            opcode,
+            payload,
+            ..
+        } = frame;
+        match opcode {
+            OP_CLOSE => {
+                // echo the status code back, then we're done.
+                write_frame(OP_CLOSE, payload.get(..2).unwrap_or_default(), out);
+                self.closed = true;
+                return Ok(());
+            }
+            OP_PING => {
+                write_frame(OP_PONG, &payload, out);
+                return Ok(());
+            }
+            OP_PONG => return Ok(()),
+            OP_CONTINUATION if self.opcode != 0 => {}
+            OP_TEXT | OP_BINARY if self.opcode == 0 => self.opcode = opcode,
+            _ => return Err(CLOSE_PROTOCOL_ERROR),
+        }
+
+        self.message.extend_from_slice(&payload);
+        if self.message.len() > WS_MAX_MESSAGE {
+            return Err(CLOSE_TOO_BIG);
+        }
+        if !fin {
+            return Ok(());
+        }
+        let data = core::mem::take(&mut self.message);
+        let message = match core::mem::take(&mut self.opcode) {
+            OP_TEXT => WsMessage::Text(String::from_utf8(data).map_err(|_| CLOSE_INVALID_DATA)?),
+            _ => WsMessage::Binary(data),
+        };
+        if let Some(reply) = self.handler.on_message(message) {
+            write_message(reply, out);
+        }
+        Ok(())
+    }
+
+    fn close(&mut self, code: u16, out: &mut Vec<u8>) {
+        write_frame(OP_CLOSE, &code.to_be_bytes(), out);
+        self.closed = true;
+    }
+}
+
+// the next frame in `buf`, None while it isn't complete, or the status code to close with.
+fn parse_frame(buf: &[u8]) -> Result<Option<Frame>, u16> {
+    let [first, second, ..] = *buf else {
+        return Ok(None);
+    };
+    let (fin, opcode) = (first & 0x80 != 0, first & 0x0f);
+    // no extensions were negotiated, and clients always mask.
+    if first & 0x70 != 0 || second & 0x80 == 0 {
+        return Err(CLOSE_PROTOCOL_ERROR);
+    }
+    let (len, mut pos) = match second & 0x7f {
+        126 => match buf.get(2..4) {
+            Some(x) => (u16::from_be_bytes([x[0], x[1]]) as usize, 4),
+            None => return Ok(None),
+        },
+        127 => return Err(CLOSE_TOO_BIG),
+        x => (x as usize, 2),
+    };
+    // control frames are short and never fragmented.
+    if opcode & 0x8 != 0 && (len > 125 || !fin) {
+        return Err(CLOSE_PROTOCOL_ERROR);
+    }
+    if len > WS_MAX_MESSAGE {
+        return Err(CLOSE_TOO_BIG);
+    }
+
+    let Some(mask) = buf.get(pos..pos + 4) else {
+        return Ok(None);
+    };
+    pos += 4;
+    let Some(payload) = buf.get(pos..pos + len) else {
+        return Ok(None);
+    };
+    let payload = payload
+        .iter()
+        .zip(mask.iter().cycle())
+        .map(|(x, key)| x ^ key)
+        .collect();
+    Ok(Some(Frame {
+        fin,
+        opcode,
+        payload,
+        len: pos + len,
+    }))
+}
+
+fn write_message(message: WsMessage, out: &mut Vec<u8>) {
+    match message {
+        WsMessage::Text(x) => write_frame(OP_TEXT, x.as_bytes(), out),
+        WsMessage::Binary(x) => write_frame(OP_BINARY, &x, out),
+    }
+}
+
+// an unmasked, unfragmented frame, payloads are at most 64K.
+fn write_frame(opcode: u8, payload: &[u8], out: &mut Vec<u8>) {
+    out.push(0x80 | opcode);
+    if payload.len() < 126 {
+        out.push(payload.len() as u8);
+    } else {
+        out.push(126);
+        out.extend_from_slice(&(payload.len() as u16).to_be_bytes());
+    }
+    out.extend_from_slice(payload);
+}
+
+fn sha1(data: &[u8]) -> [u8; 20] {
+    let mut h: [u32; 5] = [0x67452301, 0xefcdab89, 0x98badcfe, 0x10325476, 0xc3d2e1f0];
+    let mut input = Vec::from(data);
+    input.push(0x80);
+    while input.len() % 64 != 56 {
+        input.push(0);
+    }
+    input.extend_from_slice(&(data.len() as u64 * 8).to_be_bytes());
+
+    for block in input.chunks(64) {
+        let mut w = [0u32; 80];
+        for (word, bytes) in w.iter_mut().zip(block.chunks(4)) {
+            *word = u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]);
+        }
+        for i in 16..80 {
+            w[i] = (w[i - 3] ^ w[i - 8] ^ w[i - 14] ^ w[i - 16]).rotate_left(1);
+        }
+
+        let [mut a, mut b, mut c, mut d, mut e] = h;
+        for (i, word) in w.iter().enumerate() {
+            let (f, k) = match i {
+                0..=19 => ((b & c) | (!b & d), 0x5a827999),
+                20..=39 => (b ^ c ^ d, 0x6ed9eba1),
+                40..=59 => ((b & c) | (b & d) | (c & d), 0x8f1bbcdc),
+                _ => (b ^ c ^ d, 0xca62c1d6),
+            };
+            let temp = a
+                .rotate_left(5)
+                .wrapping_add(f)
+                .wrapping_add(e)
+                .wrapping_add(k)
+                .wrapping_add(*word);
+            (e, d, c, b, a) = (d, c, b.rotate_left(30), a, temp);
+        }
+        for (x, y) in h.iter_mut().zip([a, b, c, d, e]) {
+            *x = x.wrapping_add(y);
+        }
+    }
+
+    let mut digest = [0u8; 20];
+    for (bytes, x) in digest.chunks_mut(4).zip(h) {
+        bytes.copy_from_slice(&x.to_be_bytes());
+    }
+    digest
+}
+
+pub fn base64(data: &[u8]) -> String {
+    const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+    let mut out = String::new();
+    for chunk in data.chunks(3) {
+        let bits = chunk
+            .iter()
+            .enumerate()
+            .fold(0u32, |bits, (i, x)| bits | (*x as u32) << (16 - 8 * i));
+        for i in 0..4 {
+            match i <= chunk.len() {
+                true => out.push(ALPHABET[(bits >> (18 - 6 * i)) as usize & 63] as char),
+                false => out.push('='),
+            }
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::http::HttpParser;
+
+    fn hex(data: &[u8]) -> String {
+        data.iter().map(|x| alloc::format!("{x:02x}")).collect()
+    }
+
+    // FIPS 180-2 appendix A, and the empty message.
+    #[test]
+    fn sha1_known_answers() {
+        assert_eq!(hex(&sha1(b"")), "da39a3ee5e6b4b0d3255bfef95601890afd80709");
+        assert_eq!(
+            hex(&sha1(b"abc")),
+            "a9993e364706816aba3e25717850c26c9cd0d89d"
+        );
+        // the padding doesn't fit the last block, it takes another one.
+        let two_blocks = b"abcdbcdecdefdefgefghfghighijhijkijkljklmklmnlmnomnopnopq";
+        assert_eq!(
+            hex(&sha1(two_blocks)),
+            "84983e441c3bd26ebaae4aa1f95129e5e54670f1"
+        );
+        let million = alloc::vec![b'a'; 1_000_000];
+        assert_eq!(
+            hex(&sha1(&million)),
+            "34aa973cd4c4daa4f61eeb2bdbad27316534016f"
+        );
+    }
+
+    // RFC 4648 10.
+    #[test]
+    fn base64_known_answers() {
+        let vectors = [
+            ("", ""),
+            ("f", "Zg=="),
+            ("fo", "Zm8="),
+            ("foo", "Zm9v"),
+            ("foob", "Zm9vYg=="),
+            ("fooba", "Zm9vYmE="),
+            ("foobar", "Zm9vYmFy"),
+        ];
+        for (data, encoded) in vectors {
+            assert_eq!(base64(data.as_bytes()), encoded);
+        }
+        assert_eq!(base64(&[0xfb, 0xff, 0xbf]), "+/+/");
+    }
+
+    // the handshake of RFC 6455 1.3.
+    #[test]
+    fn accept_key() {
+        struct Nothing;
+        impl WebSocketHandler for Nothing {
+            fn on_message(&mut self, _message: WsMessage) -> Option<WsMessage> {
+                None
+            }
+        }
+
+        let request = HttpParser::default()
+            .push(
+                b"GET /ws/led HTTP/1.1\r\nUpgrade: websocket\r\nConnection: Upgrade\r\n\
+                  Sec-WebSocket-Key: dGhlIHNhbXBsZSBub25jZQ==\r\nSec-WebSocket-Version: 13\r\n\r\n",
+            )
+            .unwrap();
+        let response = accept(&request, Box::new(Nothing)).unwrap();
+        let key = response
+            .headers
+            .iter()
+            .find(|(name, _)| *name == "Sec-WebSocket-Accept")
+            .map(|(_, value)| value.as_str());
+        assert_eq!(key, Some("s3pPLMBiTxaQ9kYGzzhZRbK+xOo="));
+        assert!(response.upgrade.is_some());
+    }
+}
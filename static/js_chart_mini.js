@@ -0,0 +1 @@
+class js_chart{constructor(t,i,s,h,a,l){if(this.axisXtxt="x",this.axisYtxt="y",this.Ymax=0,this.Ymin=0,this.decimalX=0,this.decimalY=0,this.margv=5,this.margh=5,this.canvasbkcol="rgba(15,15,15,0)",this.canvasfrw=2,this.canvasfrcol="rgba(255,255,255,0.2)",this.marsize=8,this.marw=2,this.drawaxis=!0,this.drawarrow=!0,this.al=18,this.aw=9,this.axcol="rgba(155,155,155,1)",this.axw=2,this.axisdesc=!0,this.axdesccol="rgba(255,255,255,1)",this.drawdesc=!0,this.dgroup=!0,this.drawmark=!0,this.descol="rgba(204,204,204,1)",this.descfpx=15,this.descfontmod=" italic ",this.descfont="px Courier New",this.rotdescX=!0,this.drw0x=!0,this.drw0y=!0,this.hmarshift=!1,this.addmaxmarg=.05,this.drawmesh=!0,this.meshframe=!0,this.mshcol="rgb(200,200,200, 0.7)",this.meshlw=1,this.chartzone=!1,this.chartzonecol="rgba(120, 120, 120, 1)",this.drawzone=!1,this.drawzfrm=!0,this.drwfracol="rgba(150,  0,  0, 0.5)",this.drwfilcol="rgba(250,250,250, 0.3)",this.crXYline=!0,this.crXjump=!0,this.crYjumpM=!1,this.crYjumpP=!0,this.crHint=!0,this.crpointhint=!0,this.crpointfill=!0,this.crlinetomouse=!1,this.crlinetosmallhint=!0,this.crlinecol="rgb(0,0,255)",this.crlinewidth=1,this.crXlinedash=[7,7],this.crYlinedash=[7,7],this.smallhint=!0,this.hintwithctrl=!0,this.hintfillcol="rgba(150, 200, 150, 0.7)",this.hintfillhitcol="rgba(200, 200, 200, 1)",this.hintframewidth=3,this.hintrectcol="rgba(50, 50, 50, 0.7)",this.hintfpx=11,this.hintfontmod=" italic ",this.hintfont="px Courier New",this.hinttxtcol="rgb(0, 0, 50)",this.hintpointw=5,this.hintlinecol="rgba(50, 50, 50, 1)",this.hintpointfill="rgba(50, 50, 150, 0.5)",this.hintpointcolfromdraw=!0,this.hintpointlwdth=1,this.hintcolfrwdth=.5,this.hintlinetoMcol="rgba(250, 0, 0, 1)",this.hintlinetoMwidth=.5,this.hintmaxalpha=!1,this.hintdatashadow=!0,this.hintshadowcol="rgba(150, 150, 150, 0.2)",this.linecol="rgba(250, 0, 0, 0.6)",this.linewidth=2,this.linepoints=!1,this.linepointsize=4,this.linepointcol="rgba(250, 0, 0, 0.7)",this.arealine="rgb(250, 0, 250)",this.areafill="rgba(250, 0, 250, 0.3)",this.areawidth=2,this.areapoints=!1,this.areapointsize=5,this.areapointcol="rgb(250, 0, 250)",this.beziercurve=!0,this.beziercnst=3,this.bezierlvloff=3,this.barline="rgb(0, 250, 250)",this.barfill="rgba(0, 250, 250, 0.3)",this.barlw=1,this.barpoints=!1,this.barpointsize=5,this.barpointcol="rgba(0, 250, 250, 0.3)",this.barperc=.75,this.bigmax=1e4,this.allmaxmin=!0,this.multimsdown=!0,this.multimsdowncnt=3,this.multimstout=1e3,this.multimsdowncol="rgba(0, 0, 0, 0.15)",this.hintselectshadow=!0,this.hintselectcol="rgba(0, 0, 0, 0.3)",this.mouseclickLR=!0,this.LRsize=.2,this.hintzoomcol="rgba(0, 250, 0, 0.15)",this.undozoompx=20,this.islegend=!1,this.legmarg=5,this.legpos=0,this.legstr=[],this.legtop=0,this.legleft=0,this.legbottom=0,this.legright=0,this.legframew=1,this.legframecol="rgba(250, 250, 250, 0.7)",this.legfillcol="rgba(150, 150, 150, 0.5)",this.legpx=12,this.legfontmod=" italic ",this.legfont="px Courier New",this.legtxtcol="rgba(  0, 250, 250, 1)",void 0===t)return-1;if(void 0===i)return-2;if(void 0===s)return-3;if(this.data=i,this.style=s,this.desc=h,void 0!==a&&(this.axisXtxt=a),void 0!==l&&(this.axisYtxt=l),this.container=t,this.ctx,this.ctxl2,this.canvas,this.layer2,this.canvas=document.createElement("canvas"),this.canvas.id="canvas",this.layer2=document.createElement("canvas"),this.layer2.id="layer2",this.canvas.style.zindex=1,this.layer2.style.zindex=2,this.canvas.style.order="1",this.layer2.style.order="2",this.layer2.style.position="absolute",this.canvas.style.position="absolute",document.getElementById(t).appendChild(this.canvas),document.getElementById(t).appendChild(this.layer2),this.ctx=this.canvas.getContext("2d"),this.ctxl2=this.layer2.getContext("2d"),this.alldatalength=0,this.datalength=0,this.from=0,this.zoom=!1,Array.isArray(this.data))for(let t=0;t<this.data.length;t++)void 0!==this.data[t]&&this.alldatalength<this.data[t].length&&(this.alldatalength=this.data[t].length);this.datalength=this.alldatalength,this.to=this.alldatalength,this.eventsactivated=!1,this.pointclicked=!1,this.xdiv=0,this.wght=0,this.barcnt=0,this.barnr=0,this.marhpx=0,this.zlvl=0,this.lvlv=this.margh+2*this.marsize+this.legleft,this.dcorr=1,this.aYtxt="Y",this.lft=0,this.top=0,this.rgt=0,this.bot=0,this.zerox=0,this.drt=0,this.drb=0,this.drl=0,this.drr=0,this.drv=0,this.allpoints=!1}update(t,i,s,h,a){if(void 0!==t&&(this.data=t),void 0!==i&&(this.style=i),void 0!==s&&(this.desc=s),void 0!==h&&(this.axisXtxt=h),void 0!==a&&(this.axisYtxt=a),this.alldatalength=0,Array.isArray(this.data))for(let t=0;t<this.data.length;t++)void 0!==this.data[t]&&this.alldatalength<this.data[t].length&&(this.alldatalength=this.data[t].length);this.datalength>this.alldatalength&&(this.from=0,this.to=this.alldatalength,this.zoom=!1),self.allpoints=!1,this.draw()}draw_FromTo(t,i){return t<0||t>=this.alldatalength||i<0||i>this.alldatalength||t>i||t==i?-1:(this.from=t,this.to=i,this.zoom=!0,0==this.from&&this.to==this.alldatalength&&(this.zoom=!1),void this.draw())}draw_FromCount(t,i){return t<0||t>this.alldatalength-2||i<=1?-1:(t+i>this.alldatalength&&(i=this.alldatalength-t),this.from=t,this.to=t+i,this.zoom=!0,0==this.from&&this.to==this.alldatalength&&(this.zoom=!1),void this.draw())}redraw(){this.draw()}draw(){let t=document.getElementById(this.container);this.canvas.style.left=0,this.canvas.style.top=0,this.canvas.width=t.offsetWidth,this.canvas.height=t.offsetHeight,this.layer2.style.left=0,this.layer2.style.top=0,this.layer2.width=t.offsetWidth,this.layer2.height=t.offsetHeight,this.zlvl=0,this.lvlv=this.margh+2*this.marsize+this.legleft,this.zlvl=this.drb-this.zerox,this.lft=this.margh+this.legleft,this.top=this.margv+this.legtop,this.rgt=this.ctx.canvas.clientWidth-this.margh-this.legright,this.bot=this.ctx.canvas.clientHeight-this.margv-this.legbottom,this.zerox=0,this.drt=this.top+1.5*this.al*this.drawaxis+!this.drawaxis*this.al*.3,this.drt=this.top+(this.drawaxis?1.5*this.al:this.descfpx/2),this.drb=this.bot-this.marsize,this.drl=this.lvlv,this.drr=this.rgt-(this.drawaxis?1.5*this.al:this.descfpx/2),this.drv=this.drb-this.drt,this.make_chart()}make_chart(){let t=this,i=0,s=0,h={minv:0,maxv:0},a={cnt:0,factor:1,weight:1},l={cnt:0,factor:1,weight:1},e={cnt:0,div:1},r=0,o=0;function c(i){if(void 0===i)return{min:0,max:0};let s=0,h=0,a=0,l=i.length;t.zoom&&!t.allmaxmin&&(a=t.from,l=t.to);for(let t=a;t<l;t++)s=i[t]<s?i[t]:s,h=i[t]>h?i[t]:h;return{min:s,max:h}}function d(t,i){if(0==i||0==t)return{cnt:1,factor:0,weight:0,value:0};t=Math.abs(t),i=Math.abs(i);let s=1,h=0,a=0;if(t>1)do{if(h=.1,a=Math.abs(t/(h*s)),a<=i)break;if(h=.2,a=Math.abs(t/(h*s)),a<=i)break;if(h=.25,a=Math.abs(t/(h*s)),a<=i)break;if(h=.5,a=Math.abs(t/(h*s)),a<=i)break;if(h=1,a=Math.abs(t/(h*s)),a<=i)break;if(h=2,a=Math.abs(t/(h*s)),a<=i)break;if(h=2.5,a=Math.abs(t/(h*s)),a<=i)break;if(h=5,a=Math.abs(t/(h*s)),a<=i)break;s*=10}while(a>i);else{do{if(h=8,a=Math.abs(t*s/h),a>=i)break;if(h=7.5,a=Math.abs(t*s/h),a>=i)break;if(h=6,a=Math.abs(t*s/h),a>=i)break;if(h=5,a=Math.abs(t*s/h),a>=i)break;if(h=2.5,a=Math.abs(t*s/h),a>=i)break;if(h=2,a=Math.abs(t*s/h),a>=i)break;if(h=1,a=Math.abs(t*s/h),a>=i)break;s*=10}while(a<i);Math.abs(t*s/h)>i&&(8==h&&(h=1,s/=10),7.5==h&&(h=8),7==h&&(h=7.5),5==h&&(h=7),2.5==h&&(h=5),2==h&&(h=2.5),1==h&&(h=2),a=Math.abs(t*s/h)),s=1/s}return{cnt:a,factor:h,weight:s,value:h*s}}function n(t,i,s){let h;if(void 0===t)return s;for(let s=0;s<t.length;s++)if(void 0!==t[s]&&(h=t[s].split("="),h[0]==i))return h[1];return s}function x(t,i){if(void 0===t)return t;if(!t.includes("rgba"))return t;let s=t.split(",");return s[3]=i+")",s[0]+","+s[1]+","+s[2]+","+s[3]}function m(h,a){if(void 0===h)return;let l=0,e=0;l=0!=s?Math.abs((t.zlvl-t.drt)/s):0,e=0!=i?Math.abs((t.drb-t.zlvl)/i):0,t.wght=Math.max(l,e);let r=n(a,"linecolor",t.linecol),o=n(a,"linewidth",t.linewidth),c="true"==n(a,"points",t.linepoints).toString(),d=n(a,"pointsize",t.linepointsize),m=n(a,"pointcolor",t.linepointcol),f="true"==n(a,"beziercurve",t.beziercurve).toString(),v=n(a,"beziercnst",t.beziercnst);null==n(a,"pointcolor",null)&&(m=x(r,.3)),t.ctx.strokeStyle=r,t.ctx.fillStyle=r,t.ctx.lineWidth=o;let g=!1,b=0,p=0,z=0,w=0;for(let i=0;i<t.datalength;i++){let s=void 0!==h[i+0+t.from*t.zoom]&&null!=h[i+0+t.from*t.zoom],a=void 0!==h[i+1+t.from*t.zoom]&&null!=h[i+1+t.from*t.zoom];i==t.datalength-1&&(a=!1),b=t.lvlv+i*t.marhpx/t.xdiv+t.hmarshift*(t.marhpx/2),z=s?t.zlvl-h[i+t.from*t.zoom]*t.wght*t.dcorr:t.zlvl,s&&!g&&(t.ctx.beginPath(),a?(t.ctx.moveTo(b,z),t.ctx.lineTo(b,z)):(t.ctx.save,t.ctx.strokeStyle=x(m,1),t.ctx.fillStyle=m,t.ctx.beginPath(),t.ctx.arc(t.lvlv+i*t.marhpx/t.xdiv+t.hmarshift*(t.marhpx/2),t.zlvl-h[i+t.from*t.zoom]*t.wght*t.dcorr,d,0,2*Math.PI),t.ctx.stroke(),t.ctx.fill(),t.ctx.restore)),s&&a&&(f?(p=t.lvlv+(i+1)*t.marhpx/t.xdiv+t.hmarshift*(t.marhpx/2),w=t.zlvl-h[i+1+t.from*t.zoom]*t.wght*t.dcorr,t.ctx.bezierCurveTo(b+t.marhpx/v,z,p-t.marhpx/v,w,p,w)):t.ctx.lineTo(b,z)),s&&!a&&(t.ctx.lineTo(b,z),t.ctx.stroke(),t.ctx.closePath(),t.ctx.beginPath()),!s||g||a||(i+1<t.datalength?t.ctx.lineTo(b+t.marhpx,z):(t.ctx.save,t.ctx.strokeStyle=x(m,1),t.ctx.fillStyle=m,t.ctx.beginPath(),t.ctx.arc(t.lvlv+i*t.marhpx/t.xdiv+t.hmarshift*(t.marhpx/2),t.zlvl-h[i+t.from*t.zoom]*t.wght*t.dcorr,d,0,2*Math.PI),t.ctx.stroke(),t.ctx.fill(),t.ctx.restore)),g=s}if(c||t.allpoints){t.ctx.beginPath(),t.ctx.strokeStyle=x(m,1),t.ctx.fillStyle=m,t.ctx.arc(t.lvlv+t.hmarshift*(t.marhpx/2),t.zlvl-h[0+t.from*t.zoom]*t.wght*t.dcorr,d,0,2*Math.PI),t.ctx.stroke(),t.ctx.fill();for(let i=1;i<t.datalength;i++)t.ctx.beginPath(),t.ctx.arc(t.lvlv+i*t.marhpx/t.xdiv+t.hmarshift*(t.marhpx/2),t.zlvl-h[i+t.from*t.zoom]*t.wght*t.dcorr,d,0,2*Math.PI),t.ctx.stroke(),t.ctx.fill()}}function f(i){return"bar"==n(t.style[i],"type","")}if(this.datalength=this.alldatalength,this.wght=0,this.barcnt=0,this.barnr=0,this.marhpx=0,Array.isArray(this.data))for(let a=0;a<this.data.length;a++)f(a)&&(t.barcnt++,t.hmarshift=!0),h=c(this.data[a]),h.min<i&&(i=h.min),h.max>s&&(s=h.max);s+=s*this.addmaxmarg,i+=i*this.addmaxmarg,this.Ymax>0&&(s=this.Ymax),this.Ymin<0&&(i=this.Ymin),(this.decimalX<0||this.decimalX>5)&&(this.decimalX=0),(this.decimalY<0||this.decimalY>5)&&(this.decimalY=0),this.desc=[];for(let t=0;t<this.datalength;t++)this.desc.push(t+1);this.ctx.beginPath(),this.ctx.clearRect(0,0,this.ctx.canvas.width,this.ctx.canvas.height),this.ctx.fillStyle=this.canvasbkcol,this.ctx.lineWidth=this.canvasfrw,this.ctx.fillRect(0,0,this.ctx.canvas.width,this.ctx.canvas.height),this.ctx.stroke(),this.ctx.closePath(),this.ctx.strokeStyle=this.canvasfrcol,this.drawzfrm&&(this.ctx.beginPath(),this.ctx.rect(0,0,this.ctx.canvas.width,this.ctx.canvas.height),this.ctx.stroke(),this.ctx.closePath()),this.ctx.lineWidth=1,this.ctx.font=this.descfontmod+this.descfpx+this.descfont,this.ctx.lineCap="round",this.ctx.lineJoin="miter",this.ctx.miterLimit=1,this.dcorr=1,this.aYtxt=t.axisYtxt.toString();let v=Math.abs(Math.max(Math.abs(s),Math.abs(i)));if(v*Math.pow(10,this.decimalY)<1){let h=1,a=0;if(v>0)for(;v*h<10&&(h*=10,a++,!(a>10)););a>0&&(this.aYtxt="*(10^-"+a+")"+t.axisYtxt.toString(),i*=h,s*=h,this.dcorr=h),0==this.decimalY&&(this.decimalY=2)}if(v=Math.abs(Math.max(Math.abs(s),Math.abs(i))),v>this.bigmax){let h=1,a=0;for(;v/h>this.bigmax&&(h*=10,a++,!(a>10)););a>0&&(this.aYtxt="*(10^"+a+")"+t.axisYtxt.toString(),i/=h,s/=h,this.dcorr=1/h),this.decimalY=0}this.zoom?this.datalength=this.to-this.from:this.datalength=this.alldatalength,s>=0&&0==i&&(this.zerox=0),i<0&&0==s&&(this.zerox=this.drb-this.drt),s>0&&i<0&&(this.zerox=Math.abs(i/(s-i)*this.drv)),this.zlvl=this.drb-this.zerox,0==this.datalength&&(this.zlvl=this.drb);let g=0,b=0;for(let t=this.from*this.zoom;t<this.desc.length;t++)b=this.ctx.measureText(this.desc[t].toLocaleString(void 0,{useGrouping:this.dgroup,minimumFractionDigits:this.decimalX,maximumFractionDigits:this.decimalX})).width,b>g&&(g=b);this.drb-this.zlvl<g&&(this.drb=this.bot-this.marsize/2-g-this.descfpx/2,this.drv=this.drb-this.drt,s>0&&i<0?this.zerox=Math.abs(i/(s-i)*this.drv):s>=0?this.zerox=0:i<0&&(this.zerox=this.drb-this.drt),this.zlvl=this.drb-this.zerox);let p=Math.round(Math.abs((this.zlvl-this.drt)/this.descfpx)),z=Math.round(Math.abs((this.drb-this.zlvl)/this.descfpx)),w=p+z;a=d(s,p),p=a.cnt;let u=a.value;l=d(i,z),z=l.cnt;let y=l.value,M=Math.abs((this.zlvl-this.drt)/p),k=Math.abs((this.drb-this.zlvl)/z),T=Math.max(M,k);M=T,k=T,u>y&&(y=u),y>u&&(u=y),p=Math.floor(Math.abs((this.zlvl-this.drt)/T)),z=Math.floor(Math.abs((this.drb-this.zlvl)/T)),w=p+z;let S=this.ctx.measureText(s.toLocaleString(void 0,{useGrouping:this.dgroup,minimumFractionDigits:this.decimalY,maximumFractionDigits:this.decimalY})).width+this.descfpx,Y=this.ctx.measureText(i.toLocaleString(void 0,{useGrouping:this.dgroup,minimumFractionDigits:this.decimalY,maximumFractionDigits:this.decimalY})).width+this.descfpx,P=this.lft+this.marsize/2+Math.max(S,Y);if(P>this.lvlv&&(this.lvlv=P,this.drl=this.lvlv),r=Math.round(Math.abs((this.lvlv-this.drr)/this.descfpx))+1,e=function(t,i){let s=t,h=1,a=1;do{if(h=1,s=Math.abs(t/(h*a)),s<=i)break;if(h=2,s=Math.abs(t/(h*a)),s<=i)break;if(h=5,s=Math.abs(t/(h*a)),s<=i)break;a*=10}while(s>i);return{cnt:s,div:h*a}}(this.datalength-1,r),r=e.cnt,this.xdiv=e.div,o=Math.min(this.datalength,r)+1*this.hmarshift,this.marhpx=(this.drr-this.lvlv)/o,this.bezierlvloff>=0&&this.datalength/r>this.bezierlvloff&&(this.beziercurve=!1),this.chartzone&&(this.ctx.strokeStyle=this.chartzonecol,this.ctx.rect(this.lft,this.top,this.rgt-this.lft,this.bot-this.top),this.ctx.stroke()),this.drawzone&&(this.ctx.strokeStyle=this.drwfracol,this.ctx.fillStyle=this.drwfilcol,this.ctx.beginPath(),this.drawzfrm&&this.ctx.rect(this.drl,this.drt,this.drr-this.drl,this.drb-this.drt),this.ctx.fillRect(this.drl,this.drt,this.drr-this.drl,this.drb-this.drt),this.ctx.stroke()),this.drawmesh){this.ctx.lineWidth=this.meshlw,this.ctx.strokeStyle=this.mshcol,this.ctx.fillStyle=this.mshcol,this.meshframe&&(this.ctx.rect(this.drl,this.drt,this.drr-this.drl,this.drb-this.drt),this.ctx.stroke());let t=0,i=this.zlvl-t*M;for(;i>=this.drt;)this.ctx.beginPath(),this.ctx.moveTo(this.lvlv,this.zlvl-t*M),this.ctx.lineTo(this.drr,this.zlvl-t*M),this.ctx.stroke(),t++,i=this.zlvl-t*M;for(t=1,i=this.zlvl+t*k;i<=this.drb;)this.ctx.beginPath(),this.ctx.moveTo(this.lvlv,this.zlvl+t*k),this.ctx.lineTo(this.drr,this.zlvl+t*k),t++,i=this.zlvl+t*k,this.ctx.stroke()}if(this.drawmesh){this.ctx.strokeStyle=this.mshcol,this.ctx.fillStyle=this.mshcol;let t=0;t=1*this.hmarshift,this.meshframe&&(t=1);let i=0;for(i=this.lvlv+t*this.marhpx-this.hmarshift*(this.marhpx/2);i<=this.drr&&!(t>o);)this.ctx.beginPath(),this.ctx.moveTo(i,this.drt),this.ctx.lineTo(i,this.drb),this.ctx.stroke(),t++,i=this.lvlv+t*this.marhpx-this.hmarshift*(this.marhpx/2)}if(this.drawmark){this.ctx.lineWidth=this.marw,this.ctx.strokeStyle=this.descol,this.ctx.fillStyle=this.descol,this.ctx.textBaseline="middle",this.ctx.textAlign="end";let t=0,h=0,a=!1;if(s>0)for(t=1*!this.drw0y,a=!0,h=this.zlvl-t*M;h>=this.drt;)this.ctx.beginPath(),this.ctx.moveTo(this.lvlv-this.marsize/2,h),this.ctx.lineTo(this.lvlv+this.marsize/2,h),this.drawdesc&&this.ctx.fillText((t*u).toLocaleString(void 0,{useGrouping:this.dgroup,minimumFractionDigits:this.decimalY,maximumFractionDigits:this.decimalY}),this.lvlv-this.marsize,h),t++,h=this.zlvl-t*M,this.ctx.stroke();if(i<0)for(t=a?1:1*!this.drw0y,h=this.zlvl+t*k;h<=this.drb;)this.ctx.beginPath(),this.ctx.moveTo(this.lvlv-this.marsize/2,h),this.ctx.lineTo(this.lvlv+this.marsize/2,h),this.drawdesc&&this.ctx.fillText((-t*y).toLocaleString(void 0,{useGrouping:this.dgroup,minimumFractionDigits:this.decimalY,maximumFractionDigits:this.decimalY}),this.lvlv-this.marsize,h),t++,h=this.zlvl+t*k,this.ctx.stroke()}if(this.drawmark){this.ctx.lineWidth=this.marw,this.ctx.strokeStyle=this.descol,this.ctx.fillStyle=this.descol,this.ctx.textBaseline="top",this.ctx.textAlign="center";let t=0;if(r>0){t=1*!this.drw0x;let i=this.lvlv+t*this.marhpx+this.hmarshift*(this.marhpx/2);for(;i<=this.drr&&!(t>o);){if(this.ctx.beginPath(),this.ctx.moveTo(i,this.zlvl-this.marsize/2),this.ctx.lineTo(i,this.zlvl+this.marsize/2),this.drawdesc)if(this.rotdescX){this.ctx.save(),this.ctx.textBaseline="middle",this.ctx.textAlign="end",this.ctx.translate(this.drl,this.drb),this.ctx.rotate(-.5*Math.PI);let i=t*this.marhpx+this.hmarshift*(this.marhpx/2);void 0!==this.desc[t*this.xdiv+this.from*this.zoom]&&this.ctx.fillText(this.desc[t*this.xdiv+this.from*this.zoom].toLocaleString(void 0,{useGrouping:this.dgroup,minimumFractionDigits:this.decimalX,maximumFractionDigits:this.decimalX}),this.drb-this.zlvl-this.marsize,i+this.descfpx/1*(0==t)*!this.hmarshift),this.ctx.restore()}else void 0!==this.desc[t*this.xdiv]&&this.ctx.fillText(this.desc[t*this.xdiv+this.from*this.zoom],i-(0==t)*this.descfpx/2,this.zlvl+this.marsize);t++,i=this.lvlv+t*this.marhpx+this.hmarshift*(this.marhpx/2),this.ctx.stroke()}}}if(this.drawaxis&&(this.ctx.strokeStyle=this.axcol,this.ctx.fillStyle=this.axcol,this.ctx.lineWidth=this.axw,this.ctx.font=this.descfontmod+this.descfpx+this.descfont,this.ctx.beginPath(),this.ctx.moveTo(this.lvlv,this.zlvl),this.ctx.lineTo(this.rgt-3*this.axw*this.drawarrow,this.zlvl),this.ctx.moveTo(this.lvlv,this.bot-(this.bot-this.drb)/2),this.ctx.lineTo(this.lvlv,this.top+3*this.axw*this.drawarrow),this.ctx.stroke(),this.drawarrow&&(this.ctx.beginPath(),this.ctx.lineTo(this.rgt,this.zlvl),this.ctx.lineTo(this.rgt-this.al,this.zlvl-this.aw/2),this.ctx.lineTo(this.rgt-this.al,this.zlvl+this.aw/2),this.ctx.lineTo(this.rgt,this.zlvl),this.ctx.fillStyle=this.axcol,this.ctx.moveTo(this.lvlv,this.top),this.ctx.lineTo(this.lvlv-this.aw/2,this.top+this.al),this.ctx.lineTo(this.lvlv+this.aw/2,this.top+this.al),this.ctx.lineTo(this.lvlv,this.top),this.ctx.fillStyle=this.axcol,this.ctx.fill()),this.ctx.beginPath(),this.axisdesc)){this.ctx.fillStyle=this.axdesccol;let i=this.ctx.measureText(t.axisXtxt).width;if(i+this.drr+this.descfpx/2>this.rgt){this.ctx.textBaseline="top",this.ctx.textAlign="end";let s=0;i+this.descfpx>this.drb-this.zlvl&&(this.ctx.textAlign="start",s=2*this.descfpx),this.ctx.save(),this.ctx.translate(this.drr,this.zlvl),this.ctx.rotate(-.5*Math.PI),this.ctx.fillText(t.axisXtxt,-this.descfpx+s,.5*this.descfpx),this.ctx.restore()}else this.ctx.textAlign="start",this.ctx.textBaseline="top",this.ctx.fillText(t.axisXtxt,this.drr+this.descfpx/2,this.zlvl+this.descfpx/2);this.ctx.textAlign="start",this.ctx.textBaseline="middle",this.ctx.fillStyle=this.axdesccol,this.ctx.fillText(this.aYtxt,this.lvlv+this.aw/1,this.top+this.al/2)}!function(){if(void 0!==t.style){for(let i=0;i<t.data.length;i++)t.style[i];for(let i=0;i<t.data.length;i++)void 0!==t.style[i]&&"line"==n(t.style[i],"type","")&&m(t.data[i],t.style[i])}}()}}
\ No newline at end of file